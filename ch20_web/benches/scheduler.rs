@@ -0,0 +1,72 @@
+// cargo bench --bench scheduler
+// 比较两种调度方式处理大量短小 CPU job 的耗时：
+//   Shared       所有 worker 抢同一把锁(Mutex<VecDeque> + Condvar，原来的 Mutex<mpsc::Receiver> 换过来的)
+//   WorkStealing 每个 worker 有自己的本地队列，空了再去全局队列批量拿，或者偷兄弟的
+// 单核机器上看不出区别，要在多核上跑
+use std::hint::black_box;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ch20_web::threadpool::{Scheduler, ThreadPool};
+
+const JOBS: u64 = 200_000;
+const RUNS: usize = 5;
+
+// 一个很短的 CPU job，几百纳秒
+fn work(seed: u64) -> u64 {
+    let mut x = seed;
+    for _ in 0..100 {
+        x = x.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+    }
+    x
+}
+
+// producers 个线程一起往 pool 里塞 job，返回全部做完的耗时(drop pool 会等队列清空)
+fn run_once(scheduler: Scheduler, workers: usize, producers: u64) -> Duration {
+    let pool = Arc::new(ThreadPool::builder().min(workers).max(workers).scheduler(scheduler).build());
+    let sink = Arc::new(AtomicU64::new(0));
+    let start = Instant::now();
+    let handles: Vec<_> = (0..producers)
+        .map(|p| {
+            let pool = Arc::clone(&pool);
+            let sink = Arc::clone(&sink);
+            thread::spawn(move || {
+                for i in 0..JOBS / producers {
+                    let sink = Arc::clone(&sink);
+                    pool.execute(move || {
+                        sink.fetch_add(black_box(work(p * JOBS + i)) & 1, Ordering::Relaxed);
+                    });
+                }
+            })
+        })
+        .collect();
+    for h in handles {
+        h.join().unwrap();
+    }
+    drop(Arc::into_inner(pool).expect("producers are done"));
+    let elapsed = start.elapsed();
+    black_box(sink.load(Ordering::Relaxed));
+    elapsed
+}
+
+fn bench(name: &str, scheduler: Scheduler, workers: usize, producers: u64) {
+    let mut times: Vec<Duration> = (0..RUNS).map(|_| run_once(scheduler, workers, producers)).collect();
+    times.sort();
+    let median = times[RUNS / 2];
+    println!(
+        "{name:<14} workers={workers} producers={producers}  median {:>8.2?}  best {:>8.2?}  ({:.0} jobs/s)",
+        median,
+        times[0],
+        JOBS as f64 / median.as_secs_f64()
+    );
+}
+
+fn main() {
+    let workers = thread::available_parallelism().map_or(4, |n| n.get()).max(4);
+    for producers in [1, 4] {
+        bench("Shared", Scheduler::Shared, workers, producers);
+        bench("WorkStealing", Scheduler::WorkStealing, workers, producers);
+    }
+}
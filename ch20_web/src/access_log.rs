@@ -0,0 +1,241 @@
+use std::fmt::Write as _;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::request::{Method, Request};
+use crate::response::Response;
+use crate::router::{Middleware, Next};
+
+/// Request header that carries the request ID, on the request and on the response.
+pub const REQUEST_ID: &str = "X-Request-Id";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Common Log Format, followed by the latency and the request ID.
+    Common,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Middleware that writes one access-log line per request through `log`
+/// (target `ch20_web::access`, level info), and tags every request with an ID.
+///
+/// The ID is taken from the request's `X-Request-Id` header if the client
+/// (or a proxy in front) sent a plausible one: at most 64 bytes of
+/// `[A-Za-z0-9._-]`. Otherwise a random UUID is generated and replaces the
+/// request header, so handlers see it too. Either way it is echoed back in
+/// the response's `X-Request-Id`.
+///
+/// Register it first with `router.wrap(AccessLog::new(LogFormat::Common))`
+/// so the latency covers the other middleware as well.
+pub struct AccessLog {
+    format: LogFormat,
+}
+
+impl AccessLog {
+    pub fn new(format: LogFormat) -> AccessLog {
+        AccessLog { format }
+    }
+}
+
+impl Middleware for AccessLog {
+    fn handle(&self, req: &mut Request, next: Next<'_>) -> Response {
+        let id = match req.header(REQUEST_ID) {
+            Some(id) if valid_id(id) => id.to_string(),
+            _ => {
+                let id = uuid::Uuid::new_v4().to_string();
+                req.headers.insert(REQUEST_ID, id.clone());
+                id
+            }
+        };
+        let start = Instant::now();
+        let mut resp = next.run(req);
+        let latency = start.elapsed();
+        resp.headers.insert(REQUEST_ID, id.clone());
+
+        if log::log_enabled!(target: "ch20_web::access", log::Level::Info) {
+            // HEAD 的响应不发 body；流式 body 按它声明的长度算
+            let bytes = if req.method == Method::Head { 0 } else { resp.body.len() };
+            let entry = Entry { req, status: resp.status.as_u16(), bytes, latency, id: &id, time: SystemTime::now() };
+            let line = match self.format {
+                LogFormat::Common => entry.common(),
+                LogFormat::Json => entry.json(),
+            };
+            log::info!(target: "ch20_web::access", "{}", line);
+        }
+        resp
+    }
+}
+
+// 客户端给的 id 会原样写进日志和响应头，空格、引号之类的会伪造日志字段，太长会撑大每一行日志
+fn valid_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 64 && id.bytes().all(|b| b.is_ascii_alphanumeric() || b"._-".contains(&b))
+}
+
+struct Entry<'a> {
+    req: &'a Request,
+    status: u16,
+    bytes: u64,
+    latency: Duration,
+    id: &'a str,
+    time: SystemTime,
+}
+
+impl Entry<'_> {
+    fn remote(&self) -> String {
+        self.req.remote_addr.map_or("-".to_string(), |a| a.ip().to_string())
+    }
+
+    fn target(&self) -> String {
+        match &self.req.query {
+            Some(q) => format!("{}?{}", self.req.path, q),
+            None => self.req.path.clone(),
+        }
+    }
+
+    fn latency_ms(&self) -> f64 {
+        self.latency.as_secs_f64() * 1000.0
+    }
+
+    // 127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] "GET / HTTP/1.1" 200 2326 0.412ms 5f0c...
+    fn common(&self) -> String {
+        let bytes = if self.bytes == 0 { "-".to_string() } else { self.bytes.to_string() };
+        format!(
+            "{} - - [{}] \"{} {} {}\" {} {} {:.3}ms {}",
+            self.remote(),
+            clf_time(self.time),
+            self.req.method.as_str(),
+            self.target(),
+            self.req.version.as_str(),
+            self.status,
+            bytes,
+            self.latency_ms(),
+            self.id
+        )
+    }
+
+    fn json(&self) -> String {
+        format!(
+            "{{\"time\":\"{}\",\"remote_addr\":\"{}\",\"method\":\"{}\",\"path\":\"{}\",\"version\":\"{}\",\
+             \"status\":{},\"bytes\":{},\"latency_ms\":{:.3},\"request_id\":\"{}\"}}",
+            clf_time(self.time),
+            self.remote(),
+            json_escape(self.req.method.as_str()),
+            json_escape(&self.target()),
+            self.req.version.as_str(),
+            self.status,
+            self.bytes,
+            self.latency_ms(),
+            json_escape(self.id)
+        )
+    }
+}
+
+// path 里可能有任意字符
+fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// CLF 的时间格式 10/Oct/2000:13:55:36 +0000，统一用 UTC
+fn clf_time(t: SystemTime) -> String {
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let secs = t.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (y, m, d) = civil_from_days((secs / 86400) as i64);
+    let rem = secs % 86400;
+    format!(
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        d,
+        MONTHS[m as usize - 1],
+        y,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+// 1970-01-01 之后的天数转成年月日，算法见 http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(z: i64) -> (i64, u32, u32) {
+    let z = z + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    (y, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::RequestParser;
+    use crate::router::Router;
+
+    fn request(raw: &str) -> Request {
+        let mut parser = RequestParser::new();
+        parser.feed(raw.as_bytes());
+        let mut req = parser.parse().unwrap().unwrap();
+        req.remote_addr = Some("10.0.0.1:4321".parse().unwrap());
+        req
+    }
+
+    #[test]
+    fn request_id_is_generated_or_echoed() {
+        let mut router = Router::new();
+        router.wrap(AccessLog::new(LogFormat::Common));
+        router.get("/", |req: &Request| Response::ok().text(req.header(REQUEST_ID).unwrap_or("").to_string()));
+
+        let resp = router.handle(&mut request("GET / HTTP/1.1\r\n\r\n"));
+        let id = resp.headers.get(REQUEST_ID).unwrap().to_string();
+        assert!(uuid::Uuid::parse_str(&id).is_ok());
+        // handler 看到的是同一个 id
+        assert_eq!(resp.body.as_bytes().unwrap(), id.as_bytes());
+
+        let resp = router.handle(&mut request("GET / HTTP/1.1\r\nX-Request-Id: abc-1.2_3\r\n\r\n"));
+        assert_eq!(resp.headers.get(REQUEST_ID), Some("abc-1.2_3"));
+
+        // 不像 id 的值换成新生成的
+        let long = "a".repeat(65);
+        for bad in ["a b", "x\"y", long.as_str()] {
+            let resp = router.handle(&mut request(&format!("GET / HTTP/1.1\r\nX-Request-Id: {bad}\r\n\r\n")));
+            let id = resp.headers.get(REQUEST_ID).unwrap().to_string();
+            assert!(uuid::Uuid::parse_str(&id).is_ok(), "{bad}");
+            assert_eq!(resp.body.as_bytes().unwrap(), id.as_bytes());
+        }
+    }
+
+    #[test]
+    fn formats() {
+        let req = request("GET /a%20b?x=\"1\" HTTP/1.1\r\n\r\n");
+        let entry = Entry {
+            req: &req,
+            status: 404,
+            bytes: 0,
+            latency: Duration::from_micros(1500),
+            id: "id-1",
+            time: UNIX_EPOCH + Duration::from_secs(971_185_336),
+        };
+        assert_eq!(
+            entry.common(),
+            "10.0.0.1 - - [10/Oct/2000:13:42:16 +0000] \"GET /a%20b?x=\"1\" HTTP/1.1\" 404 - 1.500ms id-1"
+        );
+        assert_eq!(
+            entry.json(),
+            "{\"time\":\"10/Oct/2000:13:42:16 +0000\",\"remote_addr\":\"10.0.0.1\",\"method\":\"GET\",\
+             \"path\":\"/a%20b?x=\\\"1\\\"\",\"version\":\"HTTP/1.1\",\"status\":404,\"bytes\":0,\
+             \"latency_ms\":1.500,\"request_id\":\"id-1\"}"
+        );
+    }
+}
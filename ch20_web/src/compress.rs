@@ -0,0 +1,227 @@
+use std::io::{self, Write};
+
+use flate2::Compression as Level;
+use flate2::write::{GzEncoder, ZlibEncoder};
+
+use crate::request::Request;
+use crate::response::{Body, Response, StatusCode};
+use crate::router::{Middleware, Next};
+
+// 流式 body(静态文件)超过这个大小就不压缩了，压缩要先整个读进内存
+const MAX_BUFFERED: u64 = 4 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    // HTTP 里的 "deflate" 其实是 zlib 格式(RFC 1950)，不是裸的 deflate 流
+    Deflate,
+}
+
+impl Encoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+}
+
+/// Middleware that gzip- or deflate-compresses text responses when the
+/// client's `Accept-Encoding` allows it.
+///
+/// Only bodies of at least `min_size` bytes with a textual `Content-Type`
+/// are compressed; responses that already have a `Content-Encoding`, partial
+/// (`206`) responses and streamed bodies over 4 MiB are left alone. A strong
+/// `ETag` is turned into a weak one, since the compressed bytes differ.
+pub struct Compression {
+    min_size: u64,
+    level: Level,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::new()
+    }
+}
+
+impl Compression {
+    pub fn new() -> Compression {
+        Compression {
+            min_size: 1024,
+            level: Level::default(),
+        }
+    }
+
+    /// Smallest body worth compressing, 1 KiB by default.
+    pub fn min_size(mut self, bytes: u64) -> Compression {
+        self.min_size = bytes;
+        self
+    }
+
+    /// zlib level from 0 (none) to 9 (best), 6 by default.
+    pub fn level(mut self, level: u32) -> Compression {
+        self.level = Level::new(level);
+        self
+    }
+
+    fn compress(&self, resp: &mut Response, encoding: Encoding) -> io::Result<()> {
+        let body = std::mem::replace(&mut resp.body, Body::empty()).into_bytes()?;
+        let compressed = match encoding {
+            Encoding::Gzip => {
+                let mut e = GzEncoder::new(Vec::new(), self.level);
+                e.write_all(&body)?;
+                e.finish()?
+            }
+            Encoding::Deflate => {
+                let mut e = ZlibEncoder::new(Vec::new(), self.level);
+                e.write_all(&body)?;
+                e.finish()?
+            }
+        };
+        resp.body = Body::Bytes(compressed);
+        resp.headers.insert("Content-Encoding", encoding.as_str());
+        if let Some(etag) = resp.headers.get("ETag").filter(|t| !t.starts_with("W/")) {
+            let weak = format!("W/{etag}");
+            resp.headers.insert("ETag", weak);
+        }
+        Ok(())
+    }
+}
+
+impl Middleware for Compression {
+    fn handle(&self, req: &mut Request, next: Next<'_>) -> Response {
+        let mut resp = next.run(req);
+        if !compressible(&resp) {
+            return resp;
+        }
+        // 不管这次压没压缩，响应都随 Accept-Encoding 变化，缓存要知道
+        if !resp.headers.has_token("Vary", "Accept-Encoding") && !resp.headers.has_token("Vary", "*") {
+            resp.headers.append("Vary", "Accept-Encoding");
+        }
+        if resp.body.len() < self.min_size || matches!(resp.body, Body::Reader { len, .. } if len > MAX_BUFFERED) {
+            return resp;
+        }
+        let Some(encoding) = negotiate(req.header("Accept-Encoding")) else {
+            return resp;
+        };
+        if let Err(e) = self.compress(&mut resp, encoding) {
+            // body 已经被取走了，只能回 500
+            log::warn!("failed to compress response: {}", e);
+            return Response::new(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        resp
+    }
+}
+
+// 只压缩文本类的内容；图片、zip 之类本身就压缩过了，再压只会浪费 CPU
+fn compressible(resp: &Response) -> bool {
+    if resp.status == StatusCode::PARTIAL_CONTENT || resp.headers.contains("Content-Encoding") {
+        return false;
+    }
+    let Some(ct) = resp.headers.get("Content-Type") else { return false };
+    let mime = ct.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    mime.starts_with("text/")
+        || mime.ends_with("+json")
+        || mime.ends_with("+xml")
+        || matches!(
+            mime.as_str(),
+            "application/json" | "application/javascript" | "application/xml" | "application/wasm"
+        )
+}
+
+/// Picks the encoding for an `Accept-Encoding` header value: the acceptable
+/// one with the highest q-value, gzip on a tie. `None` means identity.
+pub fn negotiate(accept: Option<&str>) -> Option<Encoding> {
+    let accept = accept?;
+    let mut gzip = None;
+    let mut deflate = None;
+    let mut any = None;
+    for item in accept.split(',') {
+        let mut parts = item.split(';');
+        let coding = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+        // 没写 q 就是 1；写错了当成 0，宁可不压缩
+        let q = parts
+            .map(str::trim)
+            .find_map(|p| p.strip_prefix("q=").or_else(|| p.strip_prefix("Q=")))
+            .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())
+            .unwrap_or(0.0);
+        match coding.as_str() {
+            "gzip" | "x-gzip" => gzip = Some(q),
+            "deflate" => deflate = Some(q),
+            "*" => any = Some(q),
+            _ => {}
+        }
+    }
+    // 没单独列出来的编码取 * 的 q 值
+    let gzip = gzip.or(any).unwrap_or(0.0);
+    let deflate = deflate.or(any).unwrap_or(0.0);
+    if gzip > 0.0 && gzip >= deflate {
+        Some(Encoding::Gzip)
+    } else if deflate > 0.0 {
+        Some(Encoding::Deflate)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    use flate2::read::{GzDecoder, ZlibDecoder};
+
+    use crate::request::RequestParser;
+    use crate::router::Router;
+
+    #[test]
+    fn negotiation() {
+        assert_eq!(negotiate(None), None);
+        assert_eq!(negotiate(Some("gzip, deflate, br")), Some(Encoding::Gzip));
+        assert_eq!(negotiate(Some("gzip;q=0.5, deflate")), Some(Encoding::Deflate));
+        assert_eq!(negotiate(Some("gzip;q=0, deflate;q=0")), None);
+        assert_eq!(negotiate(Some("br, *;q=0.1")), Some(Encoding::Gzip));
+        assert_eq!(negotiate(Some("*;q=0, deflate")), Some(Encoding::Deflate));
+        assert_eq!(negotiate(Some("identity")), None);
+    }
+
+    fn get(router: &Router, path: &str, accept: &str) -> Response {
+        let mut parser = RequestParser::new();
+        parser.feed(format!("GET {path} HTTP/1.1\r\nAccept-Encoding: {accept}\r\n\r\n").as_bytes());
+        router.handle(&mut parser.parse().unwrap().unwrap())
+    }
+
+    #[test]
+    fn compresses_large_text_bodies() {
+        let text = "hello compression ".repeat(200);
+        let mut router = Router::new();
+        router.wrap(Compression::new());
+        let body = text.clone();
+        router.get("/text", move |_: &Request| Response::ok().text(body.clone()).header("ETag", "\"v1\""));
+        router.get("/small", |_: &Request| Response::ok().text("tiny"));
+        router.get("/png", |_: &Request| Response::ok().header("Content-Type", "image/png").body(vec![0u8; 4096]));
+
+        let resp = get(&router, "/text", "gzip");
+        assert_eq!(resp.headers.get("Content-Encoding"), Some("gzip"));
+        assert_eq!(resp.headers.get("Vary"), Some("Accept-Encoding"));
+        assert_eq!(resp.headers.get("ETag"), Some("W/\"v1\""));
+        let mut out = String::new();
+        GzDecoder::new(resp.body.as_bytes().unwrap()).read_to_string(&mut out).unwrap();
+        assert_eq!(out, text);
+
+        let resp = get(&router, "/text", "deflate");
+        let mut out = String::new();
+        ZlibDecoder::new(resp.body.as_bytes().unwrap()).read_to_string(&mut out).unwrap();
+        assert_eq!(out, text);
+
+        // 客户端不接受压缩时也要带 Vary
+        let resp = get(&router, "/text", "identity");
+        assert!(!resp.headers.contains("Content-Encoding"));
+        assert_eq!(resp.headers.get("Vary"), Some("Accept-Encoding"));
+
+        assert!(!get(&router, "/small", "gzip").headers.contains("Content-Encoding"));
+        let resp = get(&router, "/png", "gzip");
+        assert!(!resp.headers.contains("Content-Encoding"));
+        assert!(!resp.headers.contains("Vary"));
+    }
+}
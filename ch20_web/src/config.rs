@@ -0,0 +1,334 @@
+// 启动参数：先读 TOML 配置文件(如果给了 --config)，再用命令行参数覆盖，最后统一校验。
+// 配置文件里的相对路径相对于配置文件所在目录，而不是当前工作目录
+use std::fmt;
+use std::io;
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::conn::ConnConfig;
+use crate::request::Limits;
+use crate::router::Router;
+use crate::server::Server;
+
+pub const USAGE: &str = "\
+options:
+    --config <file>          TOML config file, overridden by the flags below
+    --bind <addr>            address to listen on, repeat for several (replaces the file's list)
+    --workers <n>            number of pool workers
+    --root <dir>             directory with hello.html and 404.html
+    --idle-timeout <secs>    how long a keep-alive connection may stay idle
+    --request-timeout <secs> time to send a whole request once it has started
+    --write-timeout <secs>   how long a write to a client that does not read may block
+    --shutdown-timeout <secs>
+    --max-header-bytes <n>   request line plus headers
+    --max-headers <n>        number of header fields
+    --max-body-bytes <n>
+    --max-conns-per-ip <n>   open connections per client address, unlimited by default
+    --log-level <level>      off, error, warn, info, debug or trace";
+
+const LOG_LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+
+/// Everything the demo server can be configured with. In TOML:
+///
+/// ```toml
+/// bind = ["0.0.0.0:7878", "[::]:7878"]
+/// workers = 4
+/// document_root = "public"
+/// log_level = "info"
+///
+/// [timeouts]
+/// idle = 5
+/// request = 10
+/// write = 10
+/// shutdown = 10
+///
+/// [limits]
+/// max_header_bytes = 8192
+/// max_headers = 100
+/// max_body_bytes = 1048576
+/// max_conns_per_ip = 16
+/// ```
+///
+/// Every key is optional and defaults to the value shown, except
+/// `document_root`, which defaults to the crate directory, and
+/// `max_conns_per_ip`, which is unlimited unless set.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: Vec<String>,
+    pub workers: usize,
+    pub document_root: PathBuf,
+    pub log_level: String,
+    pub timeouts: Timeouts,
+    pub limits: RequestLimits,
+}
+
+/// In seconds.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeouts {
+    pub idle: u64,
+    pub request: u64,
+    pub write: u64,
+    pub shutdown: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RequestLimits {
+    pub max_header_bytes: usize,
+    pub max_headers: usize,
+    pub max_body_bytes: usize,
+    pub max_conns_per_ip: Option<usize>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind: vec!["0.0.0.0:7878".to_string()],
+            workers: 4,
+            document_root: PathBuf::from(env!("CARGO_MANIFEST_DIR")),
+            log_level: "info".to_string(),
+            timeouts: Timeouts::default(),
+            limits: RequestLimits::default(),
+        }
+    }
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts { idle: 5, request: 10, write: 10, shutdown: 10 }
+    }
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        let limits = Limits::default();
+        RequestLimits {
+            max_header_bytes: limits.max_head,
+            max_headers: limits.max_headers,
+            max_body_bytes: limits.max_body,
+            max_conns_per_ip: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read { path: PathBuf, error: io::Error },
+    Parse { path: PathBuf, error: toml::de::Error },
+    /// A bad command line; the message is followed by the usage text.
+    Usage(String),
+    /// A value that parsed but makes no sense.
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read { path, error } => write!(f, "cannot read {}: {}", path.display(), error),
+            ConfigError::Parse { path, error } => write!(f, "invalid config file {}: {}", path.display(), error),
+            ConfigError::Usage(msg) => write!(f, "{msg}\n\n{USAGE}"),
+            ConfigError::Invalid(msg) => write!(f, "invalid configuration: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl ServerConfig {
+    /// Reads a TOML file. Relative paths in it are taken relative to the
+    /// file's directory. The result is not validated yet.
+    pub fn load(path: impl AsRef<Path>) -> Result<ServerConfig, ConfigError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|error| ConfigError::Read { path: path.to_path_buf(), error })?;
+        let mut config: ServerConfig =
+            toml::from_str(&text).map_err(|error| ConfigError::Parse { path: path.to_path_buf(), error })?;
+        if config.document_root.is_relative() {
+            let dir = path.parent().unwrap_or(Path::new("."));
+            config.document_root = dir.join(&config.document_root);
+        }
+        Ok(config)
+    }
+
+    /// Builds the configuration from command line arguments (without the
+    /// program name): the `--config` file if any, then the other flags on
+    /// top of it. The result is validated.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<ServerConfig, ConfigError> {
+        let args: Vec<String> = args.into_iter().collect();
+        let mut pairs = Vec::new();
+        let mut iter = args.iter();
+        while let Some(flag) = iter.next() {
+            if flag == "-h" || flag == "--help" {
+                return Err(ConfigError::Usage("usage:".to_string()));
+            }
+            if !flag.starts_with("--") {
+                return Err(ConfigError::Usage(format!("unexpected argument '{flag}'")));
+            }
+            let value = iter
+                .next()
+                .ok_or_else(|| ConfigError::Usage(format!("{flag} needs a value")))?;
+            pairs.push((flag.as_str(), value.as_str()));
+        }
+
+        // --config 不管写在哪里都先处理，其它参数才能覆盖它
+        let mut config = match pairs.iter().rev().find(|(flag, _)| *flag == "--config") {
+            Some((_, path)) => ServerConfig::load(path)?,
+            None => ServerConfig::default(),
+        };
+        let mut binds = Vec::new();
+        for (flag, value) in pairs {
+            match flag {
+                "--config" => {}
+                "--bind" => binds.push(value.to_string()),
+                "--workers" => config.workers = number(flag, value)?,
+                "--root" => config.document_root = PathBuf::from(value),
+                "--idle-timeout" => config.timeouts.idle = number(flag, value)?,
+                "--request-timeout" => config.timeouts.request = number(flag, value)?,
+                "--write-timeout" => config.timeouts.write = number(flag, value)?,
+                "--shutdown-timeout" => config.timeouts.shutdown = number(flag, value)?,
+                "--max-header-bytes" => config.limits.max_header_bytes = number(flag, value)?,
+                "--max-headers" => config.limits.max_headers = number(flag, value)?,
+                "--max-body-bytes" => config.limits.max_body_bytes = number(flag, value)?,
+                "--max-conns-per-ip" => config.limits.max_conns_per_ip = Some(number(flag, value)?),
+                "--log-level" => config.log_level = value.to_string(),
+                _ => return Err(ConfigError::Usage(format!("unknown option {flag}"))),
+            }
+        }
+        if !binds.is_empty() {
+            config.bind = binds;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks that the values can actually be used, so that mistakes show
+    /// up at startup with a message naming the offending setting.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |msg: String| Err(ConfigError::Invalid(msg));
+        if self.bind.is_empty() {
+            return invalid("bind: at least one address is needed".to_string());
+        }
+        for addr in &self.bind {
+            // 只解析不绑定；端口被占用之类的错误到 bind 时才会出现
+            if addr.to_socket_addrs().map_or(true, |mut a| a.next().is_none()) {
+                return invalid(format!("bind: '{addr}' is not a host:port address"));
+            }
+        }
+        if !(1..=1024).contains(&self.workers) {
+            return invalid(format!("workers: {} is not between 1 and 1024", self.workers));
+        }
+        if !self.document_root.is_dir() {
+            return invalid(format!("document_root: {} is not a directory", self.document_root.display()));
+        }
+        let t = &self.timeouts;
+        if [t.idle, t.request, t.write, t.shutdown].contains(&0) {
+            return invalid("timeouts: every timeout must be at least 1 second".to_string());
+        }
+        // 太小的话连一个普通浏览器请求的 header 都放不下
+        if self.limits.max_header_bytes < 1024 {
+            return invalid(format!("limits.max_header_bytes: {} is below 1024", self.limits.max_header_bytes));
+        }
+        if self.limits.max_headers == 0 {
+            return invalid("limits.max_headers: must be at least 1".to_string());
+        }
+        if self.limits.max_conns_per_ip == Some(0) {
+            return invalid("limits.max_conns_per_ip: must be at least 1".to_string());
+        }
+        if !LOG_LEVELS.contains(&self.log_level.to_ascii_lowercase().as_str()) {
+            return invalid(format!("log_level: '{}' is not one of {}", self.log_level, LOG_LEVELS.join(", ")));
+        }
+        Ok(())
+    }
+
+    pub fn conn_config(&self) -> ConnConfig {
+        ConnConfig {
+            idle_timeout: Duration::from_secs(self.timeouts.idle),
+            request_timeout: Duration::from_secs(self.timeouts.request),
+            write_timeout: Duration::from_secs(self.timeouts.write),
+            limits: Limits {
+                max_head: self.limits.max_header_bytes,
+                max_headers: self.limits.max_headers,
+                max_body: self.limits.max_body_bytes,
+            },
+        }
+    }
+
+    /// Binds every address and applies the settings to a new [`Server`].
+    pub fn server(&self, router: Router) -> io::Result<Server> {
+        let server = Server::bind_all(&self.bind, router)?
+            .workers(self.workers)
+            .conn_config(self.conn_config())
+            .shutdown_timeout(Duration::from_secs(self.timeouts.shutdown));
+        Ok(match self.limits.max_conns_per_ip {
+            Some(n) => server.max_conns_per_ip(n),
+            None => server,
+        })
+    }
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, ConfigError> {
+    value
+        .parse()
+        .map_err(|_| ConfigError::Usage(format!("{flag} expects a number, got '{value}'")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn file_then_flags() {
+        let dir = std::env::temp_dir().join(format!("ch20_web_config_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("public")).unwrap();
+        let file = dir.join("server.toml");
+        std::fs::write(
+            &file,
+            "bind = [\"127.0.0.1:8080\", \"127.0.0.1:8081\"]\nworkers = 8\ndocument_root = \"public\"\n\
+             [limits]\nmax_body_bytes = 10\n",
+        )
+        .unwrap();
+
+        let config = ServerConfig::from_args(args(&format!("--workers 2 --config {}", file.display()))).unwrap();
+        assert_eq!(config.bind, ["127.0.0.1:8080", "127.0.0.1:8081"]);
+        // 命令行优先，不管 --config 写在前面还是后面
+        assert_eq!(config.workers, 2);
+        assert_eq!(config.document_root, dir.join("public"));
+        assert_eq!(config.limits.max_body_bytes, 10);
+        assert_eq!(config.limits.max_header_bytes, 8 * 1024);
+        assert_eq!(config.timeouts, Timeouts::default());
+
+        let config =
+            ServerConfig::from_args(args(&format!("--config {} --bind 127.0.0.1:0", file.display()))).unwrap();
+        assert_eq!(config.bind, ["127.0.0.1:0"]);
+
+        std::fs::write(&file, "workers = 8\nthreads = 3\n").unwrap();
+        let err = ServerConfig::from_args(args(&format!("--config {}", file.display()))).unwrap_err();
+        assert!(err.to_string().contains("unknown field `threads`"), "{err}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn validation_errors() {
+        let err = |s: &str| ServerConfig::from_args(args(s)).unwrap_err().to_string();
+        assert!(err("--workers 0").contains("workers: 0"));
+        assert!(err("--workers four").contains("--workers expects a number"));
+        assert!(err("--bind nonsense").contains("bind: 'nonsense'"));
+        assert!(err("--root /no/such/dir").contains("document_root"));
+        assert!(err("--idle-timeout 0").contains("timeouts"));
+        assert!(err("--request-timeout 0").contains("timeouts"));
+        assert!(err("--max-conns-per-ip 0").contains("max_conns_per_ip"));
+        assert!(err("--max-header-bytes 10").contains("max_header_bytes"));
+        assert!(err("--log-level loud").contains("log_level"));
+        assert!(err("--workers").contains("--workers needs a value"));
+        assert!(err("--port 1").contains("unknown option --port"));
+        assert!(ServerConfig::from_args(Vec::new()).is_ok());
+    }
+}
@@ -0,0 +1,270 @@
+use std::io::{self, BufWriter, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::time::{Duration, Instant};
+
+use crate::request::{Limits, Method, ParseError, Request, RequestParser, Version};
+use crate::response::{Response, StatusCode};
+use crate::router::Router;
+use crate::server::Tracked;
+
+#[derive(Debug, Clone)]
+pub struct ConnConfig {
+    // keep-alive 连接在两个请求之间最多空闲多久
+    pub idle_timeout: Duration,
+    // 收到请求的第一个字节后，整个请求(head + body)必须在这么长时间内收完，否则回 408。
+    // 只靠 idle_timeout 的话，每隔几秒发一个字节的客户端(slowloris)能一直占着 worker
+    pub request_timeout: Duration,
+    // 客户端不读响应时，write 最多阻塞这么久
+    pub write_timeout: Duration,
+    pub limits: Limits,
+}
+
+impl Default for ConnConfig {
+    fn default() -> Self {
+        ConnConfig {
+            idle_timeout: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(10),
+            write_timeout: Duration::from_secs(10),
+            limits: Limits::default(),
+        }
+    }
+}
+
+// HTTP/1.1 默认是持久连接，HTTP/1.0 要显式带上 keep-alive
+fn wants_keep_alive(req: &Request) -> bool {
+    match req.version {
+        Version::Http11 => !req.headers.has_token("Connection", "close"),
+        Version::Http10 => req.headers.has_token("Connection", "keep-alive"),
+    }
+}
+
+/// A byte stream a connection is served over: the plain socket, or a TLS
+/// session on top of it.
+pub trait Transport: Read + Write {
+    /// The underlying socket, for timeouts and shutdown.
+    fn socket(&self) -> &TcpStream;
+
+    // 关闭前的收尾，TLS 要先发 close_notify
+    fn finish(&mut self) {}
+}
+
+impl Transport for TcpStream {
+    fn socket(&self) -> &TcpStream {
+        self
+    }
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    // unix 上读超时报 WouldBlock，windows 上是 TimedOut
+    matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+}
+
+/// Serves requests on `stream` until the client asks to close, stays idle for
+/// longer than `idle_timeout`, or sends something unparsable. A request that
+/// is not complete `request_timeout` after its first byte gets a `408`.
+///
+/// Requests are read and answered strictly one after another, so pipelined
+/// requests get their responses back in order. Note that the calling pool
+/// worker is tied up for as long as the connection stays open.
+pub fn serve_connection<S: Transport>(stream: S, router: &Router, config: &ConnConfig) -> io::Result<()> {
+    serve(stream, router, config, None)
+}
+
+// tracked 不为 None 时，连接由 Server 管理，会在 shutdown 时尽快关闭
+pub(crate) fn serve<S: Transport>(
+    mut stream: S,
+    router: &Router,
+    config: &ConnConfig,
+    tracked: Option<&Tracked>,
+) -> io::Result<()> {
+    stream.socket().set_write_timeout(Some(config.write_timeout))?;
+    let remote_addr = stream.socket().peer_addr().ok();
+    let mut parser = RequestParser::with_limits(config.limits);
+
+    loop {
+        if tracked.is_some_and(Tracked::set_idle) {
+            break;
+        }
+        let mut req = match read_request(&mut stream, &mut parser, config) {
+            Ok(Some(req)) => req,
+            Ok(None) => return Ok(()),
+            Err(ParseError::Io(e)) if is_timeout(&e) => break,
+            Err(ParseError::Io(e)) => return Err(e),
+            Err(e) => {
+                // 解析出错后已经不知道下一个请求从哪开始了，只能回复后关闭
+                let status = e.status().expect("non-io parse errors have a status");
+                Response::new(status)
+                    .header("Connection", "close")
+                    .text(e.to_string())
+                    .write_to(&mut BufWriter::new(&mut stream))?;
+                break;
+            }
+        };
+
+        if let Some(t) = tracked {
+            t.set_busy();
+        }
+        req.remote_addr = remote_addr;
+
+        let head_only = req.method == Method::Head;
+        let (mut resp, keep_alive) = dispatch(router, &mut req, tracked.is_some_and(Tracked::shutting_down));
+        let upgrade = resp.upgrade.take();
+        // head 和 body 分两次 write 的话，Nagle 算法加上对端的 delayed ACK 会让下一个响应卡 40ms 左右
+        resp.write(&mut BufWriter::new(&mut stream), !head_only)?;
+        if let Some(upgrade) = upgrade {
+            // 101 之后连接归 upgrade 的 handler 管，仍然在这个 worker 上跑。
+            // 读超时保留为 idle_timeout：不然几个什么都不发的 WebSocket 客户端就能把 worker 全占住，
+            // 超时后 recv 返回错误，handler 可以退出或者自己发 ping 续命
+            stream.socket().set_read_timeout(Some(config.idle_timeout))?;
+            (upgrade.0)(&mut stream, parser.take_buffered());
+            break;
+        }
+        if !keep_alive {
+            break;
+        }
+    }
+    stream.finish();
+    linger_close(stream.socket());
+    Ok(())
+}
+
+// 和 RequestParser::read_request 一样，只是每次 read 前按剩余时间设置读超时:
+// 还没收到任何字节时等 idle_timeout，收到第一个字节后整个请求共用一个 deadline
+fn read_request<S: Transport>(
+    stream: &mut S,
+    parser: &mut RequestParser,
+    config: &ConnConfig,
+) -> Result<Option<Request>, ParseError> {
+    let mut chunk = [0u8; 4096];
+    let mut deadline = None;
+    loop {
+        if let Some(req) = parser.parse()? {
+            return Ok(Some(req));
+        }
+        let timeout = match deadline {
+            None if parser.is_idle() => config.idle_timeout,
+            None => {
+                deadline = Some(Instant::now() + config.request_timeout);
+                config.request_timeout
+            }
+            Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+        };
+        if timeout.is_zero() {
+            return Err(ParseError::Timeout);
+        }
+        stream.socket().set_read_timeout(Some(timeout))?;
+        let n = match stream.read(&mut chunk) {
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) if is_timeout(&e) && !parser.is_idle() => return Err(ParseError::Timeout),
+            Err(e) => return Err(e.into()),
+        };
+        if n == 0 {
+            return if parser.is_idle() { Ok(None) } else { Err(ParseError::UnexpectedEof) };
+        }
+        parser.feed(&chunk[..n]);
+    }
+}
+
+/// Answers a connection that will not be served (e.g. the pool is full)
+/// with `resp` and closes it, without reading the request.
+pub(crate) fn refuse(stream: TcpStream, resp: Response) {
+    // 在 accept 线程上执行，不能被一个不读数据的客户端卡住
+    let _ = stream.set_write_timeout(Some(Duration::from_secs(1)));
+    let _ = resp.header("Connection", "close").write_to(&mut &stream);
+    linger_close(&stream);
+}
+
+/// Runs `req` through the router and decides whether the connection stays
+/// open afterwards, setting the `Connection` header to match. `closing` means
+/// the server is shutting down and wants the connection closed.
+pub(crate) fn dispatch(router: &Router, req: &mut Request, closing: bool) -> (Response, bool) {
+    let mut keep_alive = wants_keep_alive(req);
+    let mut resp = router.handle(req);
+    if resp.upgrade.is_some() {
+        if !closing {
+            // 不再是 HTTP 连接了，也就谈不上 keep-alive，Connection: Upgrade 要原样保留
+            return (resp, false);
+        }
+        resp = Response::new(StatusCode::SERVICE_UNAVAILABLE).text("server is shutting down");
+    }
+    keep_alive &= !resp.headers.has_token("Connection", "close");
+    // 正在关闭的 server 处理完这个请求就断开
+    keep_alive &= !closing;
+    if !keep_alive {
+        resp.headers.insert("Connection", "close");
+    } else if req.version == Version::Http10 {
+        resp.headers.insert("Connection", "keep-alive");
+    }
+    (resp, keep_alive)
+}
+
+// 先关闭写端让客户端读到 EOF，再把它已经发来的数据读掉。
+// 如果 close 时接收缓冲区里还有数据，内核会直接回 RST，客户端可能连最后一个响应都收不到
+fn linger_close(mut stream: &TcpStream) {
+    let _ = stream.shutdown(Shutdown::Write);
+    let _ = stream.set_read_timeout(Some(Duration::from_millis(100)));
+    let mut buf = [0u8; 4096];
+    let mut drained = 0;
+    while drained < 64 * 1024 {
+        match stream.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => drained += n,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::thread;
+
+    use crate::request::Request;
+
+    fn spawn_server() -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut router = Router::new();
+            router.get("/{*name}", |req: &Request| Response::ok().text(req.path.clone()));
+            for stream in listener.incoming() {
+                let _ = serve_connection(stream.unwrap(), &router, &ConnConfig::default());
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn pipelined_requests_are_answered_in_order() {
+        let addr = spawn_server();
+        let mut client = TcpStream::connect(addr).unwrap();
+        client
+            .write_all(b"GET /a HTTP/1.1\r\n\r\nHEAD /b HTTP/1.1\r\n\r\nGET /c HTTP/1.1\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut out = String::new();
+        client.read_to_string(&mut out).unwrap();
+
+        let responses: Vec<&str> = out.split("HTTP/1.1 200 OK\r\n").skip(1).collect();
+        assert_eq!(responses.len(), 3);
+        assert!(responses[0].ends_with("Content-Length: 2\r\n\r\n/a"));
+        // HEAD 的 Content-Length 是 body 的长度，但不发送 body
+        assert!(responses[1].ends_with("Content-Length: 2\r\n\r\n"));
+        assert!(responses[2].contains("Connection: close\r\n"));
+        assert!(responses[2].ends_with("/c"));
+    }
+
+    #[test]
+    fn http10_closes_unless_keep_alive() {
+        let addr = spawn_server();
+        let mut client = TcpStream::connect(addr).unwrap();
+        client
+            .write_all(b"GET /a HTTP/1.0\r\nConnection: keep-alive\r\n\r\nGET /b HTTP/1.0\r\n\r\nGET /c HTTP/1.0\r\n\r\n")
+            .unwrap();
+        let mut out = String::new();
+        client.read_to_string(&mut out).unwrap();
+        assert!(out.contains("Connection: keep-alive\r\n"));
+        assert!(out.ends_with("/b"));
+    }
+}
@@ -2,9 +2,34 @@ use std::time::Duration;
 use std::{fs, thread};
 use std::io::prelude::*;
 use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-mod threadpool;
-use threadpool::ThreadPool;
+pub mod access_log;
+pub mod compress;
+pub mod config;
+pub mod conn;
+pub mod headers;
+pub mod mio_reactor;
+pub mod request;
+pub mod response;
+pub mod reactor;
+pub mod router;
+pub mod server;
+pub mod static_files;
+pub mod threadpool;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod websocket;
+use access_log::{AccessLog, LogFormat};
+use compress::Compression;
+use config::ServerConfig;
+use request::{Request, RequestParser};
+use response::{Response, StatusCode};
+use router::Router;
+use reactor::ReactorServer;
+use static_files::StaticFiles;
+use threadpool::{OverflowPolicy, ThreadPool};
 
 fn t1_simple() {
     let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
@@ -49,54 +74,173 @@ fn handle_conn2(mut stream: TcpStream) {
     }
 }
 
-//// 对handle_conn2的重构,并加入sleep逻辑(Page658)
-fn handle_conn3(mut stream: TcpStream) {
-    // 512 bytes bufffer
-    let mut buffer = [0; 512];
-    stream.read(&mut buffer).unwrap();
+// 页面文件相对于 root(默认是 crate 目录)，而不是当前工作目录(之前只能在仓库根目录下 cargo run)
+fn page(root: &Path, status: StatusCode, filename: &str) -> Response {
+    let path = root.join(filename);
+    match fs::read(&path) {
+        Ok(contents) => Response::new(status).html(contents),
+        Err(e) => {
+            log::warn!("failed to read {}: {}", path.display(), e);
+            Response::new(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
 
-    let get = b"GET / HTTP/1.1\r\n";
-    let sleep = b"GET /sleep HTTP/1.1\r\n";
-    let (status_line, filename) = if buffer.starts_with(get) {
-        ("HTTP/1.1 200 OK\r\n\r\n", "ch20_web/hello.html")
-    } else if buffer.starts_with(sleep) {
+/// The demo site: `/`, `/sleep` and a 404 page for everything else.
+pub fn app() -> Router {
+    app_with_root(env!("CARGO_MANIFEST_DIR"))
+}
+
+/// [`app`] with `hello.html` and `404.html` read from `root`.
+pub fn app_with_root(root: impl Into<PathBuf>) -> Router {
+    let root: Arc<Path> = root.into().into();
+    let mut router = Router::new();
+    // 日志要在 t20_webserver_main 里初始化 env_logger 才能看到，RUST_LOG 可以调级别
+    router.wrap(AccessLog::new(LogFormat::Common));
+    router.wrap(Compression::new());
+    let r = Arc::clone(&root);
+    router.get("/", move |_: &Request| page(&r, StatusCode::OK, "hello.html"));
+    let r = Arc::clone(&root);
+    router.get("/sleep", move |_: &Request| {
         thread::sleep(Duration::from_secs(5));
-        ("HTTP/1.1 200 OK\r\n\r\n", "ch20_web/hello.html")
-    }else {
-        ("HTTP/1.1 404 NOT FOUND\r\n\r\n", "ch20_web/404.html")
+        page(&r, StatusCode::OK, "hello.html")
+    });
+    // 浏览器控制台里: ws = new WebSocket("ws://localhost:7878/ws"); ws.onmessage = e => console.log(e.data); ws.send("hi")
+    router.get("/ws", |req: &Request| {
+        websocket::upgrade(req, |ws| {
+            while let Ok(msg) = ws.recv() {
+                let echo = matches!(msg, websocket::Message::Text(_) | websocket::Message::Binary(_));
+                if echo && ws.send(msg).is_err() {
+                    break;
+                }
+            }
+        })
+    });
+    router.fallback(move |_: &Request| page(&root, StatusCode::NOT_FOUND, "404.html"));
+    router
+}
+
+/// Static file server mode: every GET/HEAD is answered from `root`.
+pub fn static_files_app(root: impl Into<PathBuf>) -> Router {
+    let mut router = Router::new();
+    router.wrap(Compression::new());
+    router.get("/{*path}", StaticFiles::new(root));
+    router
+}
+
+//// 对handle_conn2的重构,并加入sleep逻辑(Page658)
+// 不再只读 512 字节然后 starts_with 比较，而是用 RequestParser 解析出完整的请求，再交给 Router 分发
+// 每个连接只处理一个请求；持久连接见 conn::serve_connection
+fn handle_conn3(mut stream: TcpStream, router: &Router) {
+    let mut parser = RequestParser::new();
+    let response = match parser.read_request(&mut stream) {
+        Ok(Some(mut req)) => router.handle(&mut req),
+        // 对端什么都没发就关闭了
+        Ok(None) => return,
+        Err(e) => {
+            println!("bad request: {}", e);
+            match e.status() {
+                Some(status) => Response::new(status).text(e.to_string()),
+                None => return,
+            }
+        }
     };
 
-    // println!("Current working directory: {:?}", std::env::current_dir().unwrap());
-    let contents = fs::read_to_string(filename).unwrap();
-    let response = format!("{}{}", status_line, contents);
-    stream.write(response.as_bytes()).unwrap();
-    stream.flush().unwrap();
+    if let Err(e) = response.header("Connection", "close").write_to(&mut stream) {
+        println!("failed to write response: {}", e);
+    }
 }
 
+// 单线程逐个处理，这里不能用 keep-alive，否则一个空闲连接就会挡住后面所有客户端
 fn t2_read_request() {
     let listener = TcpListener::bind("0.0.0.0:7878").unwrap();
+    let router = app();
 
     for stream in listener.incoming() {
         let stream = stream.unwrap();
-        handle_conn3(stream);
+        handle_conn3(stream, &router);
     }
 }
 
-fn t3_threadpool() {
-    let listener = TcpListener::bind("0.0.0.0:7878").unwrap();
-    let pool = ThreadPool::new(4);
+// 这里只监听 config 里的第一个地址，多地址见 Server::bind_all
+fn t3_threadpool(config: &ServerConfig) {
+    let listener = TcpListener::bind(&config.bind[0]).unwrap();
+    // 队列满了 execute 就阻塞，accept 跟着停下来，新连接留在内核的 backlog 里，内存不会无限增长
+    let pool = ThreadPool::bounded(config.workers, 16, OverflowPolicy::Block);
+    // 每个 job 都要 'static，所以 router 和 config 用 Arc 在 worker 之间共享
+    let router = Arc::new(app_with_root(&config.document_root));
+    let conn_config = Arc::new(config.conn_config());
 
     // for stream in listener.incoming() {
     // 用于演示pool退出作用域，调用 Drop 的情况
     for stream in listener.incoming().take(2) {
         let stream = stream.unwrap();
-        pool.execute(|| {
-            handle_conn3(stream);
+        let router = Arc::clone(&router);
+        let conn_config = Arc::clone(&conn_config);
+        pool.execute(move || {
+            // 一个 worker 在同一个连接上处理多个请求，直到客户端关闭或空闲超时
+            if let Err(e) = conn::serve_connection(stream, &router, &conn_config) {
+                println!("connection error: {}", e);
+            }
         });
     }
 }
 
+// t3_threadpool 只能靠 take(2) 演示退出；Server 支持 Ctrl-C/SIGTERM 优雅退出
+fn t4_server(config: &ServerConfig) {
+    let server = match config.server(app_with_root(&config.document_root)) {
+        Ok(server) => server.metrics("/metrics"),
+        Err(e) => {
+            println!("cannot listen on {}: {}", config.bind.join(", "), e);
+            return;
+        }
+    };
+    #[cfg(unix)]
+    server.shutdown_on_signals().unwrap();
+    for addr in server.local_addrs() {
+        println!("Listening on {}", addr);
+    }
+    server.run();
+}
+
+// 同一个 app 跑在 mio 事件循环上。和 t4 对比：开很多 keep-alive 连接不发请求，
+// t4 的 worker 全被占住，/sleep 也排不上；这里空闲连接不占 worker
+fn t5_reactor_server() {
+    let server = ReactorServer::bind("0.0.0.0:7878", app()).unwrap();
+    #[cfg(unix)]
+    server.shutdown_on_signals().unwrap();
+    println!("Listening on {} (reactor)", server.local_addr());
+    server.run().unwrap();
+}
+
+// 在仓库根目录下 cargo run --features tls(并打开 t20_webserver_main 里的 t6)。证书可以这样生成:
+// openssl req -x509 -newkey rsa:2048 -nodes -keyout key.pem -out cert.pem -subj /CN=localhost
+#[cfg(feature = "tls")]
+fn t6_https_server() {
+    let config = tls::load_config("cert.pem", "key.pem").unwrap();
+    let server = server::Server::bind("0.0.0.0:7443", app()).unwrap().tls(config);
+    #[cfg(unix)]
+    server.shutdown_on_signals().unwrap();
+    println!("Listening on https://{}", server.local_addr());
+    server.run();
+}
+
+// cargo run -- --config server.toml --workers 8，参数见 config::USAGE
 pub fn t20_webserver_main() {
+    let config = match ServerConfig::from_args(std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    // 日志级别来自配置，RUST_LOG 仍然优先；main 可能被调用多次，所以用 try_init
+    let env = env_logger::Env::default().default_filter_or(config.log_level.as_str());
+    let _ = env_logger::Builder::from_env(env).try_init();
     //t2_read_request();
-    t3_threadpool();
+    //t3_threadpool(&config);
+    t4_server(&config);
+    //t5_reactor_server();
+    //#[cfg(feature = "tls")]
+    //t6_https_server();
 }
\ No newline at end of file
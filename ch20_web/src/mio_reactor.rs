@@ -0,0 +1,192 @@
+// 原来在 async_programming::ch8_reactor_executor::reactor 里。ReactorServer 也要用它，
+// 但根 crate 依赖 ch20_web，ch20_web 不能反过来依赖根 crate，所以搬到这里，ch8 那边再 re-export。
+//
+// reactor 只管把 mio 的事件(和到期的 timer)变成 wake 调用，谁来 poll 由 waker 决定：
+// ch8 的 executor 用它唤醒 task，ReactorServer 用它唤醒连接
+use mio::{event::Source, Events, Interest, Poll, Registry, Token};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// What the reactor calls when a registered source or a timer is ready.
+pub trait Wake: Clone + Send + 'static {
+    fn wake(&self);
+}
+
+type Wakers<W> = Arc<Mutex<HashMap<usize, W>>>;
+// 按 deadline 排序，第一个就是最近要到期的；同一时刻的多个 timer 用 id 区分
+type Timers<W> = Arc<Mutex<BTreeMap<(Instant, usize), W>>>;
+
+// 用来打断 poll.poll()，id 从 1 开始递增，碰不到 usize::MAX
+const WAKE_TOKEN: Token = Token(usize::MAX);
+
+pub struct Reactor<W> {
+    wakers: Wakers<W>,
+    timers: Timers<W>,
+    registry: Registry,
+    // 新加的 timer 比 event loop 正在等的 deadline 还早时，要把它从 poll 里叫醒重新算超时；
+    // 停止 event loop 时也用它
+    waker: mio::Waker,
+    stopped: Arc<AtomicBool>,
+    next_id: AtomicUsize, // used to allocate task id
+}
+
+impl<W: Wake> Reactor<W> {
+    // 任何 mio 的 Source 都可以：TcpStream、TcpListener、UdpSocket，unix 上还有 UnixStream、pipe 等
+    pub fn register<S>(&self, source: &mut S, interest: Interest, id: usize) -> io::Result<()>
+        where S: Source + ?Sized {
+        self.registry.register(source, Token(id), interest)
+    }
+
+    /// Changes the interest of an already registered `source`, e.g. from
+    /// READABLE to WRITABLE once there is something to send.
+    pub fn reregister<S>(&self, source: &mut S, interest: Interest, id: usize) -> io::Result<()>
+        where S: Source + ?Sized {
+        self.registry.reregister(source, Token(id), interest)
+    }
+
+    pub fn set_waker(&self, waker: &W, id: usize) {
+        // 不能使用 *waker , 未实现 Copy trait, cannot move out of `*waker` which is behind a shared reference
+        self.wakers.lock().unwrap().insert(id, waker.clone());
+    }
+
+    pub fn deregister<S>(&self, source: &mut S, id: usize) -> io::Result<()>
+        where S: Source + ?Sized {
+        self.wakers.lock().map(|mut w| w.remove(&id)).unwrap();
+        self.registry.deregister(source)
+    }
+
+    /// Wakes `waker` once `deadline` has passed. Setting the same `id` and
+    /// `deadline` again only replaces the waker.
+    pub fn set_timer(&self, deadline: Instant, waker: &W, id: usize) {
+        let mut timers = self.timers.lock().unwrap();
+        let earliest = timers.keys().next().is_none_or(|&(first, _)| deadline < first);
+        timers.insert((deadline, id), waker.clone());
+        drop(timers);
+        if earliest {
+            self.waker.wake().unwrap();
+        }
+    }
+
+    pub fn remove_timer(&self, deadline: Instant, id: usize) {
+        self.timers.lock().map(|mut t| t.remove(&(deadline, id))).unwrap();
+    }
+
+    pub fn next_id(&self) -> usize {
+        /*We don’t care about any happens before/after relationships happening here;
+         we only care about not handing out the same value twice, so Ordering::Relaxed will suffice here
+         */
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+// poll 最多等到最近的 timer 到期；没有 timer 就一直等 I/O 事件
+fn next_timeout<W>(timers: &Timers<W>) -> Option<Duration> {
+    let timers = timers.lock().unwrap();
+    timers.keys().next().map(|&(deadline, _)| deadline.saturating_duration_since(Instant::now()))
+}
+
+// 取出所有已经到期的 timer，在锁外面再 wake
+fn expired<W>(timers: &Timers<W>) -> Vec<W> {
+    let mut timers = timers.lock().unwrap();
+    let now = Instant::now();
+    let mut fired = vec![];
+    while let Some(entry) = timers.first_entry() {
+        if entry.key().0 > now {
+            break;
+        }
+        fired.push(entry.remove());
+    }
+    fired
+}
+
+fn event_loop<W: Wake>(mut poll: Poll, wakers: Wakers<W>, timers: Timers<W>, stopped: Arc<AtomicBool>) {
+    let mut events = Events::with_capacity(100);
+    // ReactorHandle::drop 先设置 stopped 再 wake，这里醒来就能看到
+    while !stopped.load(Ordering::Acquire) {
+        match poll.poll(&mut events, next_timeout(&timers)) {
+            Ok(()) => {}
+            // 被信号打断(EINTR)不算出错，重新 poll 就行；线程要是退出了，所有等着的 future 都不会再被唤醒
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => panic!("Reactor: poll failed: {e}"),
+        }
+        for waker in expired(&timers) {
+            waker.wake();
+        }
+        for e in events.iter() {
+            if e.token() == WAKE_TOKEN {
+                // 重新计算超时，或者该退出了(由 while 的条件判断)
+                continue;
+            }
+            // Tokio provides some methods on the Event object to check several
+            // things about the event it reported. For our use in this example, we don’t need to filter events.
+            let Token(id) = e.token();
+            let wakers = wakers.lock().unwrap();
+
+            log::trace!("Reactor: event for {id}, getting waker ...");
+            // may be removed already
+            if let Some(waker) = wakers.get(&id) {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// Keeps a reactor running; dropping it stops the event loop and joins its thread.
+pub struct ReactorHandle<W> {
+    reactor: Arc<Reactor<W>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<W> ReactorHandle<W> {
+    pub fn reactor(&self) -> &Arc<Reactor<W>> {
+        &self.reactor
+    }
+}
+
+impl<W> Drop for ReactorHandle<W> {
+    fn drop(&mut self) {
+        self.reactor.stopped.store(true, Ordering::Release);
+        self.reactor.waker.wake().unwrap();
+        if let Some(thread) = self.thread.take() {
+            // event loop 线程 panic 了的话这里不再 panic，否则 drop 过程中 panic 会直接 abort
+            if thread.join().is_err() {
+                log::error!("Reactor: event loop thread panicked");
+            }
+        }
+    }
+}
+
+/// Starts an event loop on a new thread.
+pub fn start<W: Wake>() -> io::Result<ReactorHandle<W>> {
+    let wakers = Arc::new(Mutex::new(HashMap::new()));
+    let timers = Arc::new(Mutex::new(BTreeMap::new()));
+    let poll = Poll::new()?;
+    let registry = poll.registry().try_clone()?;
+    let waker = mio::Waker::new(poll.registry(), WAKE_TOKEN)?;
+    // for debugging purposes, I wanted to initialize it to a different start value than our Executor
+    let next_id = AtomicUsize::new(1);
+    let stopped = Arc::new(AtomicBool::new(false));
+    let reactor = Arc::new(Reactor {
+        wakers: wakers.clone(),
+        timers: timers.clone(),
+        registry,
+        waker,
+        stopped: stopped.clone(),
+        next_id,
+    });
+
+    /*
+    the best practice would be to store the JoinHandle returned from spawn so that we can join
+    the thread later on. 现在 event loop 可以通过 waker 停下来，JoinHandle 就存在 ReactorHandle 里
+     */
+    let thread = thread::spawn(move || event_loop(poll, wakers, timers, stopped));
+    Ok(ReactorHandle { reactor, thread: Some(thread) })
+}
@@ -0,0 +1,573 @@
+// 事件驱动的 server：Server 一个连接占一个 worker 线程，空闲的 keep-alive 连接也一直占着；
+// 这里所有连接的读写事件都由 mio_reactor(就是 ch8 的那个 Reactor)等着，worker 只在执行 handler 时才被占用。
+//
+// reactor 线程把事件变成 ConnWaker::wake，把连接 id 放进队列；run 的线程相当于 ch8 的 executor，
+// 从队列里取出被唤醒的连接去读写。handler 是同步的(比如 /sleep 会睡 5 秒)，不能在 run 的线程上执行，
+// 还是交给 ThreadPool，做完后把序列化好的响应放进同一个队列，再叫醒 run 的线程去写。
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Shutdown, SocketAddr, ToSocketAddrs};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use mio::net::{TcpListener, TcpStream};
+use mio::Interest;
+
+use crate::conn::{self, ConnConfig};
+use crate::mio_reactor::{self, Reactor, Wake};
+use crate::request::{Limits, Method, ParseError, RequestParser};
+use crate::response::{Response, StatusCode};
+use crate::router::Router;
+use crate::threadpool::{OverflowPolicy, PoolError, ThreadPool};
+
+// 没有事件时也要定期醒来检查空闲超时
+const TICK: Duration = Duration::from_millis(200);
+// 关了写端之后最多再等这么久让对端关闭，见 conn::linger_close
+const LINGER: Duration = Duration::from_millis(500);
+
+struct Shared {
+    local_addr: SocketAddr,
+    shutting_down: AtomicBool,
+    queue: Mutex<Queue>,
+    // queue 里有东西了，或者要 shutdown 了
+    ready: Condvar,
+    #[cfg(unix)]
+    signals: Mutex<Option<signal_hook::iterator::Handle>>,
+}
+
+impl Shared {
+    fn notify(&self, f: impl FnOnce(&mut Queue)) {
+        f(&mut self.queue.lock().unwrap());
+        self.ready.notify_one();
+    }
+
+    // 等到有事可做，最多等 timeout。shutdown 也会 notify，所以不会错过
+    fn wait(&self, timeout: Duration) -> Queue {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.woken.is_empty() && queue.done.is_empty() {
+            queue = self.ready.wait_timeout(queue, timeout).unwrap().0;
+        }
+        std::mem::take(&mut *queue)
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    // reactor 唤醒的连接(和 listener)
+    woken: Vec<usize>,
+    // worker 处理完的响应
+    done: Vec<Done>,
+}
+
+struct Done {
+    id: usize,
+    bytes: Vec<u8>,
+    keep_alive: bool,
+}
+
+/// Stops a running [`ReactorServer`] from any thread.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    shared: Arc<Shared>,
+}
+
+impl ShutdownHandle {
+    /// Stops accepting and makes `run` return once the in-flight requests are done.
+    pub fn shutdown(&self) {
+        self.shared.shutting_down.store(true, Ordering::SeqCst);
+        self.shared.notify(|_| ());
+    }
+}
+
+// 注册在 reactor 上的 waker，和 ch8 executor 的 Waker 一样只是把 id 放进队列
+#[derive(Clone)]
+struct ConnWaker {
+    id: usize,
+    shared: Arc<Shared>,
+}
+
+impl Wake for ConnWaker {
+    fn wake(&self) {
+        self.shared.notify(|q| q.woken.push(self.id));
+    }
+}
+
+struct Conn {
+    stream: TcpStream,
+    remote_addr: SocketAddr,
+    parser: RequestParser,
+    // 还没写出去的响应
+    out: Vec<u8>,
+    // 有一个请求正在 worker 上处理。处理完之前不解析下一个，pipelining 的响应才能按顺序返回
+    handling: bool,
+    // false 表示当前响应写完就关闭
+    keep_alive: bool,
+    // 对端已经关闭了写端(read 返回 0)
+    read_closed: bool,
+    last_active: Instant,
+    // 收到当前请求第一个字节的时间，请求交给 worker 后清空。超过 request_timeout 回 408
+    request_start: Option<Instant>,
+    // out 上一次有进展(变成非空或写出了数据)的时间，客户端不读响应超过 write_timeout 就关闭
+    last_write: Instant,
+    // 已经关了写端，在等对端关闭。这期间收到的数据直接丢掉
+    closing: Option<Instant>,
+    // 占用了 per_ip 里的一个名额
+    ip_slot: bool,
+}
+
+impl Conn {
+    // 读到 WouldBlock 为止。mio 是边沿触发的，这次不读完，下次就不会再有 readable 事件了。
+    // 但缓冲的数据到了 cap 就先停下，让 parser 消化掉(或者报 431/413)再接着读；返回 true 表示还没读完
+    fn fill(&mut self, cap: usize) -> io::Result<bool> {
+        let mut buf = [0u8; 4096];
+        while !self.read_closed {
+            if self.parser.buffered() >= cap {
+                return Ok(true);
+            }
+            match self.stream.read(&mut buf) {
+                Ok(0) => self.read_closed = true,
+                Ok(n) => {
+                    self.parser.feed(&buf[..n]);
+                    self.last_active = Instant::now();
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(false)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        while !self.out.is_empty() {
+            match self.stream.write(&self.out) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.out.drain(..n);
+                    self.last_active = Instant::now();
+                    self.last_write = self.last_active;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn queue(&mut self, bytes: &[u8]) {
+        self.last_active = Instant::now();
+        if self.out.is_empty() {
+            self.last_write = self.last_active;
+        }
+        self.out.extend_from_slice(bytes);
+    }
+
+    // 回一个响应，写完就关闭
+    fn refuse(&mut self, resp: Response) -> io::Result<()> {
+        let mut bytes = Vec::new();
+        resp.header("Connection", "close").write_to(&mut bytes)?;
+        self.queue(&bytes);
+        self.keep_alive = false;
+        self.request_start = None;
+        Ok(())
+    }
+
+    fn fail(&mut self, e: ParseError) -> io::Result<()> {
+        let status = e.status().unwrap_or(StatusCode::BAD_REQUEST);
+        self.refuse(Response::new(status).text(e.to_string()))
+    }
+
+    // 两个请求之间，可以随时关掉
+    fn is_idle(&self) -> bool {
+        !self.handling && self.out.is_empty() && self.parser.is_idle()
+    }
+}
+
+/// An event-driven alternative to [`Server`](crate::server::Server): one
+/// thread waits on all sockets with `mio`, and only running handlers occupy
+/// pool workers, so idle keep-alive connections cost no thread.
+///
+/// Handlers are the same `Router` as for `Server`. Response bodies are
+/// buffered in memory before being written, streamed ones included.
+pub struct ReactorServer {
+    listener: TcpListener,
+    router: Arc<Router>,
+    workers: usize,
+    queue_capacity: usize,
+    conn_config: ConnConfig,
+    max_conns_per_ip: Option<usize>,
+    shutdown_timeout: Duration,
+    shared: Arc<Shared>,
+}
+
+impl ReactorServer {
+    pub fn bind(addr: impl ToSocketAddrs, router: Router) -> io::Result<ReactorServer> {
+        let std_listener = std::net::TcpListener::bind(addr)?;
+        std_listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(std_listener);
+        let shared = Arc::new(Shared {
+            local_addr: listener.local_addr()?,
+            shutting_down: AtomicBool::new(false),
+            queue: Mutex::new(Queue::default()),
+            ready: Condvar::new(),
+            #[cfg(unix)]
+            signals: Mutex::new(None),
+        });
+        Ok(ReactorServer {
+            listener,
+            router: Arc::new(router),
+            workers: 4,
+            queue_capacity: 64,
+            conn_config: ConnConfig::default(),
+            max_conns_per_ip: None,
+            shutdown_timeout: Duration::from_secs(10),
+            shared,
+        })
+    }
+
+    /// Number of pool workers running handlers, 4 by default.
+    pub fn workers(mut self, n: usize) -> ReactorServer {
+        self.workers = n;
+        self
+    }
+
+    /// How many requests may wait for a free worker, 64 by default. Requests
+    /// beyond that get `503 Service Unavailable`, like `Server` with the
+    /// `Reject` policy. The other policies would stall the event loop
+    /// (`Block`, `CallerRuns`) or lose a response (`DropOldest`).
+    pub fn queue_capacity(mut self, capacity: usize) -> ReactorServer {
+        self.queue_capacity = capacity;
+        self
+    }
+
+    pub fn conn_config(mut self, config: ConnConfig) -> ReactorServer {
+        self.conn_config = config;
+        self
+    }
+
+    /// Caps the open connections per client IP; extra ones get `429 Too Many Requests`.
+    pub fn max_conns_per_ip(mut self, n: usize) -> ReactorServer {
+        assert!(n > 0, "max_conns_per_ip must be at least 1");
+        self.max_conns_per_ip = Some(n);
+        self
+    }
+
+    /// How long a shutdown waits for in-flight requests, 10 seconds by default.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> ReactorServer {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.shared.local_addr
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    /// Shuts the server down on the first SIGINT or SIGTERM.
+    #[cfg(unix)]
+    pub fn shutdown_on_signals(&self) -> io::Result<()> {
+        let handle = self.shutdown_handle();
+        *self.shared.signals.lock().unwrap() = Some(crate::server::on_signals(move || handle.shutdown())?);
+        Ok(())
+    }
+
+    /// Runs the event loop until `shutdown` is called.
+    pub fn run(self) -> io::Result<()> {
+        let ReactorServer {
+            mut listener,
+            router,
+            workers,
+            queue_capacity,
+            conn_config,
+            max_conns_per_ip,
+            shutdown_timeout,
+            shared,
+        } = self;
+        // drop 时停掉 reactor 线程，要在所有连接都关掉之后
+        let reactor_handle = mio_reactor::start()?;
+        let reactor = Arc::clone(reactor_handle.reactor());
+        // 先放 waker 再注册，不然注册之后马上来的事件找不到 waker 就丢了
+        let listener_id = reactor.next_id();
+        reactor.set_waker(&ConnWaker { id: listener_id, shared: Arc::clone(&shared) }, listener_id);
+        reactor.register(&mut listener, Interest::READABLE, listener_id)?;
+        let mut event_loop = EventLoop {
+            reactor,
+            pool: ThreadPool::bounded(workers, queue_capacity, OverflowPolicy::Reject),
+            router,
+            shared,
+            conns: HashMap::new(),
+            limits: conn_config.limits,
+            max_conns_per_ip,
+            per_ip: HashMap::new(),
+        };
+        // bind 和 run 之间可能已经有连接在等了，边沿触发不会再通知
+        event_loop.accept(&listener);
+
+        let mut deadline = None;
+        loop {
+            let Queue { woken, done } = event_loop.shared.wait(TICK);
+            for id in woken {
+                if id == listener_id {
+                    event_loop.accept(&listener);
+                } else {
+                    event_loop.drive(id);
+                }
+            }
+            event_loop.complete(done);
+
+            if event_loop.shutting_down() && deadline.is_none() {
+                println!("Shutting down, waiting for {} connection(s).", event_loop.conns.len());
+                event_loop.reactor.deregister(&mut listener, listener_id)?;
+                deadline = Some(Instant::now() + shutdown_timeout);
+            }
+            event_loop.sweep(&conn_config, deadline);
+            if deadline.is_some() && event_loop.conns.is_empty() {
+                break;
+            }
+        }
+
+        #[cfg(unix)]
+        if let Some(signals) = event_loop.shared.signals.lock().unwrap().take() {
+            signals.close();
+        }
+        println!("Waiting for workers to finish.");
+        // ThreadPool::drop 会 join 所有 Worker
+        drop(event_loop);
+        drop(reactor_handle);
+        Ok(())
+    }
+}
+
+struct EventLoop {
+    reactor: Arc<Reactor<ConnWaker>>,
+    pool: ThreadPool,
+    router: Arc<Router>,
+    shared: Arc<Shared>,
+    // 连接的 id 由 reactor 分配，不复用，迟到的响应找不到连接就丢掉
+    conns: HashMap<usize, Conn>,
+    limits: Limits,
+    max_conns_per_ip: Option<usize>,
+    // 每个客户端 IP 当前的连接数，只在设置了 max_conns_per_ip 时统计
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+impl EventLoop {
+    fn shutting_down(&self) -> bool {
+        self.shared.shutting_down.load(Ordering::SeqCst)
+    }
+
+    fn accept(&mut self, listener: &TcpListener) {
+        loop {
+            let (mut stream, remote_addr) = match listener.accept() {
+                Ok(accepted) => accepted,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    println!("accept error: {}", e);
+                    return;
+                }
+            };
+            let id = self.reactor.next_id();
+            self.reactor.set_waker(&ConnWaker { id, shared: Arc::clone(&self.shared) }, id);
+            // 读写都注册上，之后不用再 reregister
+            if let Err(e) = self.reactor.register(&mut stream, Interest::READABLE | Interest::WRITABLE, id) {
+                println!("failed to register connection: {}", e);
+                let _ = self.reactor.deregister(&mut stream, id);
+                continue;
+            }
+            let mut conn = Conn {
+                stream,
+                remote_addr,
+                parser: RequestParser::with_limits(self.limits),
+                out: Vec::new(),
+                handling: false,
+                keep_alive: true,
+                read_closed: false,
+                last_active: Instant::now(),
+                request_start: None,
+                last_write: Instant::now(),
+                closing: None,
+                ip_slot: false,
+            };
+            if let Some(max) = self.max_conns_per_ip {
+                let n = self.per_ip.entry(remote_addr.ip()).or_insert(0);
+                if *n < max {
+                    *n += 1;
+                    conn.ip_slot = true;
+                } else {
+                    // 和普通连接一样走事件循环写出去，不会被不读数据的客户端卡住
+                    let _ = conn.refuse(Response::new(StatusCode::TOO_MANY_REQUESTS).text("too many connections"));
+                }
+            }
+            self.conns.insert(id, conn);
+            // 可能已经有数据了
+            self.drive(id);
+        }
+    }
+
+    // 把 worker 处理完的响应交给对应的连接
+    fn complete(&mut self, done: Vec<Done>) {
+        for d in done {
+            // 连接可能已经因为出错被关掉了
+            let Some(conn) = self.conns.get_mut(&d.id) else { continue };
+            conn.handling = false;
+            conn.keep_alive &= d.keep_alive;
+            conn.queue(&d.bytes);
+            self.drive(d.id);
+        }
+    }
+
+    fn drive(&mut self, id: usize) {
+        if !self.conns.contains_key(&id) {
+            return;
+        }
+        let open = self.advance(id).unwrap_or_else(|e| {
+            println!("connection error: {}", e);
+            false
+        });
+        if !open {
+            self.close(id);
+        }
+    }
+
+    fn close(&mut self, id: usize) {
+        let mut conn = self.conns.remove(&id).unwrap();
+        if conn.ip_slot {
+            let ip = conn.remote_addr.ip();
+            if let Some(n) = self.per_ip.get_mut(&ip) {
+                *n -= 1;
+                if *n == 0 {
+                    self.per_ip.remove(&ip);
+                }
+            }
+        }
+        let _ = self.reactor.deregister(&mut conn.stream, id);
+        let _ = conn.stream.shutdown(Shutdown::Write);
+    }
+
+    // 推进一个连接：读新数据，写出缓冲的响应，再解析出下一个请求交给 worker。
+    // 返回 false 表示连接该关闭了
+    fn advance(&mut self, id: usize) -> io::Result<bool> {
+        let cap = self.limits.max_head + self.limits.max_body;
+        let conn = self.conns.get_mut(&id).unwrap();
+        loop {
+            if conn.closing.is_some() {
+                // 等对端关闭，收到的数据都丢掉；一直不关的话 sweep 会在 LINGER 之后关掉
+                while conn.fill(cap)? {
+                    conn.parser.take_buffered();
+                }
+                conn.parser.take_buffered();
+                return Ok(!conn.read_closed);
+            }
+            // 处理请求期间不读，客户端接着发的数据留在内核的接收缓冲区里，满了 TCP 会让它停下来；
+            // 处理完 complete 会再 drive 一次，到时候再读
+            let more = !conn.handling && conn.fill(cap)?;
+            conn.flush()?;
+            // 响应还没写完(等 writable 事件)，或者请求还在处理(等 waker)
+            if !conn.out.is_empty() || conn.handling {
+                return Ok(true);
+            }
+            if !conn.keep_alive {
+                // 直接 close 的话，对端还在发的数据会让内核回 RST，它可能连最后的响应都收不到
+                let _ = conn.stream.shutdown(Shutdown::Write);
+                conn.closing = Some(Instant::now());
+                continue;
+            }
+            if conn.request_start.is_none() && !conn.parser.is_idle() {
+                conn.request_start = Some(Instant::now());
+            }
+            let mut req = match conn.parser.parse() {
+                Ok(Some(req)) => req,
+                Ok(None) if more => continue,
+                // 对端关了写端，又没有完整的请求，就不会再有了
+                Ok(None) => return Ok(!conn.read_closed),
+                Err(e) => {
+                    conn.fail(e)?;
+                    continue;
+                }
+            };
+            conn.handling = true;
+            conn.request_start = None;
+            req.remote_addr = Some(conn.remote_addr);
+
+            let router = Arc::clone(&self.router);
+            let shared = Arc::clone(&self.shared);
+            let job = move || {
+                let head_only = req.method == Method::Head;
+                let closing = shared.shutting_down.load(Ordering::SeqCst);
+                // handler panic 了也要给连接一个答复，不然它会一直等下去
+                let (mut resp, keep_alive) =
+                    panic::catch_unwind(AssertUnwindSafe(|| conn::dispatch(&router, &mut req, closing)))
+                        .unwrap_or_else(|_| {
+                            (Response::new(StatusCode::INTERNAL_SERVER_ERROR).header("Connection", "close"), false)
+                        });
+                // upgrade 的 handler 是阻塞读写的，这里没法把连接交给它
+                if resp.upgrade.is_some() {
+                    resp = Response::new(StatusCode::NOT_IMPLEMENTED)
+                        .header("Connection", "close")
+                        .text("protocol upgrades need the threaded Server");
+                }
+                let mut bytes = Vec::new();
+                // 流式 body 也在这里读进内存；读失败时已经发出去的 Content-Length 对不上了，只能关闭
+                let keep_alive = match resp.write(&mut bytes, !head_only) {
+                    Ok(()) => keep_alive,
+                    Err(e) => {
+                        println!("failed to write response: {}", e);
+                        false
+                    }
+                };
+                shared.notify(|q| q.done.push(Done { id, bytes, keep_alive }));
+            };
+            // 和 Server 一样，队列满了回 503
+            if let Err(PoolError::QueueFull) = self.pool.try_execute(job) {
+                conn.handling = false;
+                conn.refuse(Response::new(StatusCode::SERVICE_UNAVAILABLE).text("server is busy"))?;
+                continue;
+            }
+            return Ok(true);
+        }
+    }
+
+    // 关掉超过 idle_timeout 的连接、不读响应超过 write_timeout 的连接，
+    // 请求超过 request_timeout 还没收完的回 408(只看 idle_timeout 的话，每隔几秒发一个字节就能一直占着连接)。
+    // shutdown 后空闲的连接马上关，过了 deadline 全部关掉
+    fn sweep(&mut self, config: &ConnConfig, deadline: Option<Instant>) {
+        let now = Instant::now();
+        let expired = deadline.is_some_and(|d| now >= d);
+        let mut stale = Vec::new();
+        let mut timed_out = Vec::new();
+        for (&id, c) in &self.conns {
+            let stalled = !c.out.is_empty() && now.duration_since(c.last_write) >= config.write_timeout;
+            let lingered = c.closing.is_some_and(|t| now.duration_since(t) >= LINGER);
+            // 有响应没写完时只看 write_timeout，否则客户端读得慢一点就会先撞上(更短的) idle_timeout
+            let idle = !c.handling
+                && c.out.is_empty()
+                && (deadline.is_some() && c.is_idle() || now.duration_since(c.last_active) >= config.idle_timeout);
+            if expired || stalled || lingered || idle {
+                stale.push(id);
+            } else if c.request_start.is_some_and(|t| now.duration_since(t) >= config.request_timeout) {
+                timed_out.push(id);
+            }
+        }
+        if expired && !stale.is_empty() {
+            println!("Shutdown timeout, closing {} connection(s).", stale.len());
+        }
+        for id in stale {
+            self.close(id);
+        }
+        for id in timed_out {
+            let conn = self.conns.get_mut(&id).unwrap();
+            if let Err(e) = conn.fail(ParseError::Timeout) {
+                println!("connection error: {}", e);
+            }
+            self.drive(id);
+        }
+    }
+}
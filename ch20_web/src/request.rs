@@ -0,0 +1,671 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
+use std::mem;
+use std::net::SocketAddr;
+
+use crate::headers::Headers;
+use crate::response::StatusCode;
+
+// request line + headers 的默认最大长度，对应 431 Request Header Fields Too Large
+const MAX_HEAD_LEN: usize = 8 * 1024;
+// body 的默认最大长度，对应 413 Content Too Large
+const MAX_BODY_LEN: usize = 1024 * 1024;
+// header 行数的默认上限，也对应 431
+const MAX_HEADERS: usize = 100;
+const READ_CHUNK: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Patch,
+    Options,
+    Trace,
+    Connect,
+    // 扩展方法(例如 WebDAV 的 PROPFIND)，原样保留
+    Other(String),
+}
+
+impl Method {
+    fn from_token(s: &str) -> Method {
+        match s {
+            "GET" => Method::Get,
+            "HEAD" => Method::Head,
+            "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "DELETE" => Method::Delete,
+            "PATCH" => Method::Patch,
+            "OPTIONS" => Method::Options,
+            "TRACE" => Method::Trace,
+            "CONNECT" => Method::Connect,
+            other => Method::Other(other.to_string()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Patch => "PATCH",
+            Method::Options => "OPTIONS",
+            Method::Trace => "TRACE",
+            Method::Connect => "CONNECT",
+            Method::Other(s) => s,
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+impl Version {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Version::Http10 => "HTTP/1.0",
+            Version::Http11 => "HTTP/1.1",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: Method,
+    // 不含 query 部分，也没有做百分号解码
+    pub path: String,
+    pub query: Option<String>,
+    pub version: Version,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+    // 由 Router 在匹配 "/users/{id}" 这类路径时填入
+    pub params: HashMap<String, String>,
+    // 由 server 填入；直接用 RequestParser 解析时是 None
+    pub remote_addr: Option<SocketAddr>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(String::as_str)
+    }
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    Io(io::Error),
+    Malformed(&'static str),
+    UnsupportedVersion,
+    HeadTooLarge,
+    TooManyHeaders,
+    BodyTooLarge,
+    // 请求开始后没在规定时间内发完，由读请求的一方判断(见 ConnConfig::request_timeout)
+    Timeout,
+    // 对端在请求还没发完时就关闭了连接
+    UnexpectedEof,
+}
+
+impl ParseError {
+    /// The status to answer with before closing the connection, or `None`
+    /// when the connection itself is broken.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            ParseError::Io(_) => None,
+            ParseError::Malformed(_) | ParseError::UnexpectedEof => Some(StatusCode::BAD_REQUEST),
+            ParseError::UnsupportedVersion => Some(StatusCode::HTTP_VERSION_NOT_SUPPORTED),
+            ParseError::HeadTooLarge | ParseError::TooManyHeaders => Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE),
+            ParseError::Timeout => Some(StatusCode::REQUEST_TIMEOUT),
+            ParseError::BodyTooLarge => Some(StatusCode::CONTENT_TOO_LARGE),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Io(e) => write!(f, "i/o error: {e}"),
+            ParseError::Malformed(what) => write!(f, "malformed request: {what}"),
+            ParseError::UnsupportedVersion => f.write_str("unsupported http version"),
+            ParseError::HeadTooLarge => f.write_str("request head too large"),
+            ParseError::TooManyHeaders => f.write_str("too many header fields"),
+            ParseError::BodyTooLarge => f.write_str("request body too large"),
+            ParseError::Timeout => f.write_str("timed out waiting for the request"),
+            ParseError::UnexpectedEof => f.write_str("connection closed in the middle of a request"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> ParseError {
+        ParseError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Chunk {
+    Size,
+    Data(usize),
+    // chunk data 后面必须跟一个 CRLF
+    DataEnd,
+    Trailers,
+}
+
+#[derive(Debug)]
+enum Framing {
+    Length(usize),
+    Chunked(Chunk),
+}
+
+#[derive(Debug)]
+enum State {
+    Head,
+    Body(Box<Request>, Framing),
+}
+
+/// Size limits enforced while parsing a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Request line plus headers, 8 KiB by default.
+    pub max_head: usize,
+    /// Number of header fields (trailers included), 100 by default.
+    pub max_headers: usize,
+    /// Decoded body, 1 MiB by default.
+    pub max_body: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_head: MAX_HEAD_LEN,
+            max_headers: MAX_HEADERS,
+            max_body: MAX_BODY_LEN,
+        }
+    }
+}
+
+/// Incremental HTTP/1.x request parser.
+///
+/// Bytes can arrive in any split: `feed` whatever was read and call `parse`
+/// until it yields a request. Bytes after the end of a request stay buffered,
+/// so pipelined requests come out one `parse` call at a time.
+pub struct RequestParser {
+    buf: Vec<u8>,
+    state: State,
+    limits: Limits,
+}
+
+impl Default for RequestParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestParser {
+    pub fn new() -> RequestParser {
+        RequestParser::with_limits(Limits::default())
+    }
+
+    pub fn with_limits(limits: Limits) -> RequestParser {
+        RequestParser { buf: Vec::new(), state: State::Head, limits }
+    }
+
+    pub fn feed(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// True when no part of a next request has been received yet.
+    pub fn is_idle(&self) -> bool {
+        matches!(self.state, State::Head) && self.buf.iter().all(|&b| b == b'\r' || b == b'\n')
+    }
+
+    /// Bytes received but not parsed yet.
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Takes the bytes buffered after the last parsed request, e.g. the first
+    /// frames of a connection that switches protocols.
+    pub fn take_buffered(&mut self) -> Vec<u8> {
+        mem::take(&mut self.buf)
+    }
+
+    /// Returns `Ok(None)` when more bytes are needed.
+    pub fn parse(&mut self) -> Result<Option<Request>, ParseError> {
+        if let State::Head = self.state {
+            match self.parse_head()? {
+                Some((req, framing)) => self.state = State::Body(Box::new(req), framing),
+                None => return Ok(None),
+            }
+        }
+        if !self.parse_body()? {
+            return Ok(None);
+        }
+        match mem::replace(&mut self.state, State::Head) {
+            State::Body(req, _) => Ok(Some(*req)),
+            State::Head => unreachable!(),
+        }
+    }
+
+    /// Reads from `r` until a whole request is buffered.
+    ///
+    /// `Ok(None)` means the peer closed the connection cleanly between requests.
+    pub fn read_request<R: Read>(&mut self, r: &mut R) -> Result<Option<Request>, ParseError> {
+        let mut chunk = [0u8; READ_CHUNK];
+        loop {
+            if let Some(req) = self.parse()? {
+                return Ok(Some(req));
+            }
+            let n = match r.read(&mut chunk) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if n == 0 {
+                return if self.is_idle() { Ok(None) } else { Err(ParseError::UnexpectedEof) };
+            }
+            self.feed(&chunk[..n]);
+        }
+    }
+
+    fn parse_head(&mut self) -> Result<Option<(Request, Framing)>, ParseError> {
+        // RFC 9112 2.2: 请求行之前多余的空行应当忽略
+        let blank = self.buf.iter().take_while(|&&b| b == b'\r' || b == b'\n').count();
+        self.buf.drain(..blank);
+
+        let max_head = self.limits.max_head;
+        let end = match find_head_end(&self.buf) {
+            Some(end) if end <= max_head => end,
+            Some(_) => return Err(ParseError::HeadTooLarge),
+            None if self.buf.len() > max_head => return Err(ParseError::HeadTooLarge),
+            None => return Ok(None),
+        };
+        let head: Vec<u8> = self.buf.drain(..end).collect();
+        let head = std::str::from_utf8(&head).map_err(|_| ParseError::Malformed("head is not utf-8"))?;
+
+        // lines() 同时处理 "\r\n" 和 "\n" 两种行尾
+        let mut lines = head.lines();
+        let (method, target, version) = parse_request_line(lines.next().unwrap_or_default())?;
+        let mut headers = Headers::new();
+        for line in lines.take_while(|l| !l.is_empty()) {
+            let (name, value) = parse_header_line(line)?;
+            headers.append(name, value);
+            if headers.len() > self.limits.max_headers {
+                return Err(ParseError::TooManyHeaders);
+            }
+        }
+
+        let (path, query) = split_target(target)?;
+        let framing = body_framing(&headers, version)?;
+        // 声明的长度超了就不用等 body 了；chunked 的只能边收边算
+        if matches!(framing, Framing::Length(len) if len > self.limits.max_body) {
+            return Err(ParseError::BodyTooLarge);
+        }
+        let req = Request {
+            method,
+            path,
+            query,
+            version,
+            headers,
+            body: Vec::new(),
+            params: HashMap::new(),
+            remote_addr: None,
+        };
+        Ok(Some((req, framing)))
+    }
+
+    // 返回 true 表示 body 已经收全
+    fn parse_body(&mut self) -> Result<bool, ParseError> {
+        let RequestParser { buf, state, limits } = self;
+        let State::Body(req, framing) = state else {
+            return Ok(true);
+        };
+        match framing {
+            Framing::Length(remaining) => {
+                let n = (*remaining).min(buf.len());
+                req.body.extend(buf.drain(..n));
+                *remaining -= n;
+                Ok(*remaining == 0)
+            }
+            Framing::Chunked(chunk) => {
+                let done = parse_chunked(buf, req, chunk, limits.max_head)?;
+                if req.body.len() > limits.max_body {
+                    return Err(ParseError::BodyTooLarge);
+                }
+                // trailer 也是 header
+                if req.headers.len() > limits.max_headers {
+                    return Err(ParseError::TooManyHeaders);
+                }
+                Ok(done)
+            }
+        }
+    }
+}
+
+// chunk-size 行和 trailer 的长度用 max_head 限制
+fn parse_chunked(buf: &mut Vec<u8>, req: &mut Request, state: &mut Chunk, max_line: usize) -> Result<bool, ParseError> {
+    loop {
+        match *state {
+            Chunk::Size => {
+                let Some(line) = take_line(buf, max_line)? else {
+                    return Ok(false);
+                };
+                // chunk extension (";name=value") 直接忽略
+                let size = line.split(';').next().unwrap_or_default().trim();
+                if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    return Err(ParseError::Malformed("invalid chunk size"));
+                }
+                let size = usize::from_str_radix(size, 16)
+                    .map_err(|_| ParseError::Malformed("chunk size overflow"))?;
+                *state = if size == 0 { Chunk::Trailers } else { Chunk::Data(size) };
+            }
+            Chunk::Data(remaining) => {
+                if buf.is_empty() {
+                    return Ok(false);
+                }
+                let n = remaining.min(buf.len());
+                req.body.extend(buf.drain(..n));
+                *state = if n == remaining { Chunk::DataEnd } else { Chunk::Data(remaining - n) };
+            }
+            Chunk::DataEnd => {
+                let Some(line) = take_line(buf, max_line)? else {
+                    return Ok(false);
+                };
+                if !line.is_empty() {
+                    return Err(ParseError::Malformed("chunk data not followed by CRLF"));
+                }
+                *state = Chunk::Size;
+            }
+            Chunk::Trailers => {
+                let Some(line) = take_line(buf, max_line)? else {
+                    return Ok(false);
+                };
+                if line.is_empty() {
+                    return Ok(true);
+                }
+                let (name, value) = parse_header_line(&line)?;
+                req.headers.append(name, value);
+            }
+        }
+    }
+}
+
+// 取出一行(不含行尾)，不完整时返回 None
+fn take_line(buf: &mut Vec<u8>, max_len: usize) -> Result<Option<String>, ParseError> {
+    let Some(pos) = buf.iter().position(|&b| b == b'\n') else {
+        if buf.len() > max_len {
+            return Err(ParseError::HeadTooLarge);
+        }
+        return Ok(None);
+    };
+    let mut line: Vec<u8> = buf.drain(..=pos).collect();
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| ParseError::Malformed("line is not utf-8"))
+}
+
+// 返回 head 结束位置(包含空行)
+fn find_head_end(buf: &[u8]) -> Option<usize> {
+    for i in 0..buf.len() {
+        if buf[i] != b'\n' {
+            continue;
+        }
+        match &buf[i + 1..] {
+            [b'\n', ..] => return Some(i + 2),
+            [b'\r', b'\n', ..] => return Some(i + 3),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+fn parse_request_line(line: &str) -> Result<(Method, &str, Version), ParseError> {
+    let mut parts = line.split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(ParseError::Malformed("invalid request line"));
+    };
+    if !is_token(method) {
+        return Err(ParseError::Malformed("invalid method"));
+    }
+    if target.is_empty() {
+        return Err(ParseError::Malformed("empty request target"));
+    }
+    let version = match version {
+        "HTTP/1.1" => Version::Http11,
+        "HTTP/1.0" => Version::Http10,
+        v if v.starts_with("HTTP/") => return Err(ParseError::UnsupportedVersion),
+        _ => return Err(ParseError::Malformed("invalid http version")),
+    };
+    Ok((Method::from_token(method), target, version))
+}
+
+fn parse_header_line(line: &str) -> Result<(&str, &str), ParseError> {
+    // 以空白开头的是已废弃的 obs-fold 续行，RFC 9112 允许直接拒绝
+    if line.starts_with([' ', '\t']) {
+        return Err(ParseError::Malformed("obsolete header line folding"));
+    }
+    let (name, value) = line
+        .split_once(':')
+        .ok_or(ParseError::Malformed("header without colon"))?;
+    if !is_token(name) {
+        return Err(ParseError::Malformed("invalid header name"));
+    }
+    Ok((name, value.trim_matches([' ', '\t'])))
+}
+
+fn split_target(target: &str) -> Result<(String, Option<String>), ParseError> {
+    let path_and_query = if target.starts_with('/') || target == "*" {
+        target
+    } else if let Some(rest) = target.strip_prefix("http://").or_else(|| target.strip_prefix("https://")) {
+        // absolute-form，发给代理的请求会带上 scheme 和 authority
+        rest.find('/').map_or("/", |i| &rest[i..])
+    } else {
+        return Err(ParseError::Malformed("invalid request target"));
+    };
+    Ok(match path_and_query.split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(query.to_string())),
+        None => (path_and_query.to_string(), None),
+    })
+}
+
+fn body_framing(headers: &Headers, version: Version) -> Result<Framing, ParseError> {
+    let lengths: Vec<&str> = headers
+        .get_all("Content-Length")
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect();
+
+    if headers.contains("Transfer-Encoding") {
+        // 两者同时出现是典型的 request smuggling 手法
+        if !lengths.is_empty() {
+            return Err(ParseError::Malformed("both Transfer-Encoding and Content-Length"));
+        }
+        if version == Version::Http10 {
+            return Err(ParseError::Malformed("Transfer-Encoding in HTTP/1.0 request"));
+        }
+        // chunked 必须是最后一个 coding，否则无法确定 body 长度
+        let last = headers
+            .get_all("Transfer-Encoding")
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .last();
+        return match last {
+            Some(t) if t.eq_ignore_ascii_case("chunked") => Ok(Framing::Chunked(Chunk::Size)),
+            _ => Err(ParseError::Malformed("unsupported transfer-encoding")),
+        };
+    }
+
+    let mut len = None;
+    for v in lengths {
+        if v.is_empty() || !v.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParseError::Malformed("invalid Content-Length"));
+        }
+        let n: usize = v.parse().map_err(|_| ParseError::Malformed("Content-Length overflow"))?;
+        if len.is_some_and(|l| l != n) {
+            return Err(ParseError::Malformed("conflicting Content-Length"));
+        }
+        len = Some(n);
+    }
+    Ok(Framing::Length(len.unwrap_or(0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_all(input: &[u8]) -> Result<Option<Request>, ParseError> {
+        let mut parser = RequestParser::new();
+        parser.feed(input);
+        parser.parse()
+    }
+
+    #[test]
+    fn split_across_reads() {
+        let raw = b"GET /users/7?verbose=1 HTTP/1.1\r\nHost: localhost\r\nX-Empty:\r\n\r\n";
+        let mut parser = RequestParser::new();
+        for b in &raw[..raw.len() - 1] {
+            parser.feed(&[*b]);
+            assert!(parser.parse().unwrap().is_none());
+        }
+        parser.feed(&raw[raw.len() - 1..]);
+        let req = parser.parse().unwrap().unwrap();
+        assert_eq!(req.method, Method::Get);
+        assert_eq!(req.path, "/users/7");
+        assert_eq!(req.query.as_deref(), Some("verbose=1"));
+        assert_eq!(req.version, Version::Http11);
+        assert_eq!(req.header("host"), Some("localhost"));
+        assert_eq!(req.header("x-empty"), Some(""));
+        assert!(parser.is_idle());
+    }
+
+    #[test]
+    fn content_length_body_and_pipelining() {
+        let raw = b"POST /a HTTP/1.1\r\nContent-Length: 5\r\n\r\nhelloGET /b HTTP/1.0\r\n\r\n";
+        let mut parser = RequestParser::new();
+        parser.feed(raw);
+        let first = parser.parse().unwrap().unwrap();
+        assert_eq!(first.method, Method::Post);
+        assert_eq!(first.body, b"hello");
+        let second = parser.parse().unwrap().unwrap();
+        assert_eq!(second.path, "/b");
+        assert_eq!(second.version, Version::Http10);
+        assert!(parser.parse().unwrap().is_none());
+    }
+
+    #[test]
+    fn chunked_body_with_trailer() {
+        let raw = b"POST /up HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+            5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nX-Checksum: abc\r\n\r\n";
+        let mut parser = RequestParser::new();
+        // 逐字节喂入，验证 chunk 状态机能跨 read 续上
+        let mut req = None;
+        for b in raw {
+            parser.feed(&[*b]);
+            if let Some(r) = parser.parse().unwrap() {
+                req = Some(r);
+            }
+        }
+        let req = req.unwrap();
+        assert_eq!(req.body, b"hello world");
+        assert_eq!(req.header("x-checksum"), Some("abc"));
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        let bad: [&[u8]; 6] = [
+            b"GET /\r\n\r\n",
+            b"GET / HTTP/1.1\r\nBad Header: x\r\n\r\n",
+            b"GET / HTTP/1.1\r\nHost: a\r\n folded\r\n\r\n",
+            b"POST / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n",
+            b"POST / HTTP/1.1\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n",
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n",
+        ];
+        for input in bad {
+            assert!(matches!(parse_all(input), Err(ParseError::Malformed(_))), "{:?}", String::from_utf8_lossy(input));
+        }
+        assert!(matches!(parse_all(b"GET / HTTP/2.0\r\n\r\n"), Err(ParseError::UnsupportedVersion)));
+    }
+
+    #[test]
+    fn head_too_large() {
+        let mut raw = b"GET / HTTP/1.1\r\nX-Big: ".to_vec();
+        raw.extend(std::iter::repeat_n(b'a', MAX_HEAD_LEN));
+        assert!(matches!(parse_all(&raw), Err(ParseError::HeadTooLarge)));
+    }
+
+    #[test]
+    fn chunk_lines_use_the_configured_head_limit() {
+        let limits = Limits { max_head: 64, ..Limits::default() };
+        let mut parser = RequestParser::with_limits(limits);
+        parser.feed(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5;");
+        parser.feed(&[b'x'; 100]);
+        assert!(matches!(parser.parse(), Err(ParseError::HeadTooLarge)));
+    }
+
+    #[test]
+    fn too_many_headers() {
+        let limits = Limits { max_headers: 2, ..Limits::default() };
+        let mut parser = RequestParser::with_limits(limits);
+        parser.feed(b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\n\r\n");
+        assert!(parser.parse().unwrap().is_some());
+        parser.feed(b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n");
+        let err = parser.parse().unwrap_err();
+        assert!(matches!(err, ParseError::TooManyHeaders));
+        assert_eq!(err.status(), Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE));
+    }
+
+    #[test]
+    fn body_too_large() {
+        let limits = Limits { max_head: 1024, max_body: 4, ..Limits::default() };
+        let mut parser = RequestParser::with_limits(limits);
+        parser.feed(b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\n");
+        assert!(matches!(parser.parse(), Err(ParseError::BodyTooLarge)));
+
+        let mut parser = RequestParser::with_limits(limits);
+        parser.feed(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n");
+        assert!(parser.parse().unwrap().is_none());
+        parser.feed(b"2\r\nde\r\n0\r\n\r\n");
+        assert!(matches!(parser.parse(), Err(ParseError::BodyTooLarge)));
+    }
+
+    #[test]
+    fn eof_in_the_middle_of_a_request() {
+        let mut parser = RequestParser::new();
+        let mut input: &[u8] = b"GET / HTTP/1.1\r\nHost";
+        assert!(matches!(parser.read_request(&mut input), Err(ParseError::UnexpectedEof)));
+
+        let mut parser = RequestParser::new();
+        let mut input: &[u8] = b"";
+        assert!(parser.read_request(&mut input).unwrap().is_none());
+    }
+}
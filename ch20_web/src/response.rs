@@ -0,0 +1,288 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::conn::Transport;
+use crate::headers::Headers;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StatusCode(u16);
+
+impl StatusCode {
+    pub const SWITCHING_PROTOCOLS: StatusCode = StatusCode(101);
+    pub const OK: StatusCode = StatusCode(200);
+    pub const CREATED: StatusCode = StatusCode(201);
+    pub const NO_CONTENT: StatusCode = StatusCode(204);
+    pub const PARTIAL_CONTENT: StatusCode = StatusCode(206);
+    pub const MOVED_PERMANENTLY: StatusCode = StatusCode(301);
+    pub const FOUND: StatusCode = StatusCode(302);
+    pub const NOT_MODIFIED: StatusCode = StatusCode(304);
+    pub const BAD_REQUEST: StatusCode = StatusCode(400);
+    pub const FORBIDDEN: StatusCode = StatusCode(403);
+    pub const NOT_FOUND: StatusCode = StatusCode(404);
+    pub const METHOD_NOT_ALLOWED: StatusCode = StatusCode(405);
+    pub const REQUEST_TIMEOUT: StatusCode = StatusCode(408);
+    pub const CONTENT_TOO_LARGE: StatusCode = StatusCode(413);
+    pub const RANGE_NOT_SATISFIABLE: StatusCode = StatusCode(416);
+    pub const UPGRADE_REQUIRED: StatusCode = StatusCode(426);
+    pub const TOO_MANY_REQUESTS: StatusCode = StatusCode(429);
+    pub const REQUEST_HEADER_FIELDS_TOO_LARGE: StatusCode = StatusCode(431);
+    pub const INTERNAL_SERVER_ERROR: StatusCode = StatusCode(500);
+    pub const NOT_IMPLEMENTED: StatusCode = StatusCode(501);
+    pub const SERVICE_UNAVAILABLE: StatusCode = StatusCode(503);
+    pub const HTTP_VERSION_NOT_SUPPORTED: StatusCode = StatusCode(505);
+
+    /// # Panics
+    /// Panics if `code` is not a three digit status code.
+    pub const fn new(code: u16) -> StatusCode {
+        assert!(code >= 100 && code <= 999);
+        StatusCode(code)
+    }
+
+    pub fn as_u16(&self) -> u16 {
+        self.0
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match self.0 {
+            101 => "Switching Protocols",
+            200 => "OK",
+            201 => "Created",
+            204 => "No Content",
+            206 => "Partial Content",
+            301 => "Moved Permanently",
+            302 => "Found",
+            304 => "Not Modified",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            408 => "Request Timeout",
+            413 => "Content Too Large",
+            416 => "Range Not Satisfiable",
+            426 => "Upgrade Required",
+            429 => "Too Many Requests",
+            431 => "Request Header Fields Too Large",
+            500 => "Internal Server Error",
+            501 => "Not Implemented",
+            503 => "Service Unavailable",
+            505 => "HTTP Version Not Supported",
+            _ => "",
+        }
+    }
+
+    // 1xx/204/304 的响应不能带 body(RFC 9110 6.4.1)
+    fn allows_body(&self) -> bool {
+        !(self.0 < 200 || self.0 == 204 || self.0 == 304)
+    }
+}
+
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.0, self.reason())
+    }
+}
+
+/// A response body: either fully in memory, or a reader of known length
+/// that is copied to the socket without being loaded first (large files).
+pub enum Body {
+    Bytes(Vec<u8>),
+    Reader { reader: Box<dyn Read + Send>, len: u64 },
+}
+
+impl Body {
+    pub fn empty() -> Body {
+        Body::Bytes(Vec::new())
+    }
+
+    pub fn len(&self) -> u64 {
+        match self {
+            Body::Bytes(b) => b.len() as u64,
+            Body::Reader { len, .. } => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The body bytes, unless it is a streamed body.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Body::Bytes(b) => Some(b),
+            Body::Reader { .. } => None,
+        }
+    }
+
+    /// Reads a streamed body into memory.
+    pub fn into_bytes(self) -> io::Result<Vec<u8>> {
+        match self {
+            Body::Bytes(b) => Ok(b),
+            Body::Reader { reader, len } => {
+                let mut buf = Vec::with_capacity(len as usize);
+                reader.take(len).read_to_end(&mut buf)?;
+                Ok(buf)
+            }
+        }
+    }
+
+    fn write_to<W: Write>(self, w: &mut W) -> io::Result<()> {
+        match self {
+            Body::Bytes(b) => w.write_all(&b),
+            Body::Reader { reader, len } => {
+                // 按块拷贝，不会把整个文件读进内存
+                let copied = io::copy(&mut reader.take(len), w)?;
+                if copied < len {
+                    // Content-Length 已经发出去了，只能断开连接
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "body shorter than its length"));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Body::Bytes(b) => write!(f, "Body::Bytes({} bytes)", b.len()),
+            Body::Reader { len, .. } => write!(f, "Body::Reader({len} bytes)"),
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(b: Vec<u8>) -> Body {
+        Body::Bytes(b)
+    }
+}
+
+impl From<&[u8]> for Body {
+    fn from(b: &[u8]) -> Body {
+        Body::Bytes(b.to_vec())
+    }
+}
+
+impl From<String> for Body {
+    fn from(s: String) -> Body {
+        Body::Bytes(s.into_bytes())
+    }
+}
+
+impl From<&str> for Body {
+    fn from(s: &str) -> Body {
+        Body::Bytes(s.as_bytes().to_vec())
+    }
+}
+
+/// Takes over the connection once a `101 Switching Protocols` response has
+/// been written, see [`websocket::upgrade`](crate::websocket::upgrade). The
+/// bytes are whatever the client sent after the request.
+pub(crate) struct Upgrade(pub(crate) UpgradeFn);
+
+pub(crate) type UpgradeFn = Box<dyn FnOnce(&mut dyn Transport, Vec<u8>) + Send>;
+
+impl fmt::Debug for Upgrade {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Upgrade")
+    }
+}
+
+/// An HTTP response, built up with the consuming `header`/`body` methods:
+///
+/// ```
+/// use ch20_web::response::{Response, StatusCode};
+/// let resp = Response::new(StatusCode::OK)
+///     .header("Content-Type", "text/plain")
+///     .body("hi");
+/// assert_eq!(resp.status, StatusCode::OK);
+/// ```
+#[derive(Debug)]
+pub struct Response {
+    pub status: StatusCode,
+    pub headers: Headers,
+    pub body: Body,
+    pub(crate) upgrade: Option<Upgrade>,
+}
+
+impl Response {
+    pub fn new(status: StatusCode) -> Response {
+        Response {
+            status,
+            headers: Headers::new(),
+            body: Body::empty(),
+            upgrade: None,
+        }
+    }
+
+    pub fn ok() -> Response {
+        Response::new(StatusCode::OK)
+    }
+
+    pub fn not_found() -> Response {
+        Response::new(StatusCode::NOT_FOUND)
+    }
+
+    /// Replaces any previous value of the header.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Response {
+        self.headers.insert(name, value);
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Body>) -> Response {
+        self.body = body.into();
+        self
+    }
+
+    pub fn html(self, body: impl Into<Body>) -> Response {
+        self.header("Content-Type", "text/html; charset=utf-8").body(body)
+    }
+
+    pub fn text(self, body: impl Into<Body>) -> Response {
+        self.header("Content-Type", "text/plain; charset=utf-8").body(body)
+    }
+
+    /// Serializes the status line, headers and body. `Content-Length` is always
+    /// derived from the body, whatever the handler put into `headers`.
+    pub fn write_to<W: Write>(self, w: &mut W) -> io::Result<()> {
+        self.write(w, true)
+    }
+
+    // HEAD 请求的响应只有 head，Content-Length 仍然是 GET 时 body 的长度
+    pub(crate) fn write<W: Write>(self, w: &mut W, include_body: bool) -> io::Result<()> {
+        let mut head = format!("HTTP/1.1 {}\r\n", self.status);
+        for (name, value) in self.headers.iter() {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                continue;
+            }
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        if self.status.allows_body() {
+            head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        head.push_str("\r\n");
+
+        w.write_all(head.as_bytes())?;
+        if include_body && self.status.allows_body() {
+            self.body.write_to(w)?;
+        }
+        w.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_with_content_length() {
+        let resp = Response::new(StatusCode::NOT_FOUND)
+            .header("Content-Length", "999")
+            .text("nope");
+        let mut out = Vec::new();
+        resp.write_to(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: 4\r\n\r\nnope"
+        );
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+
+use crate::request::{Method, Request};
+use crate::response::{Response, StatusCode};
+
+// 与 ThreadPool 的 Job 一样要求 Send + 'static，另外 Router 会被多个 worker 通过 Arc 共享，所以还要 Sync
+pub trait Handler: Send + Sync + 'static {
+    fn handle(&self, req: &Request) -> Response;
+}
+
+impl<F> Handler for F
+where
+    F: Fn(&Request) -> Response + Send + Sync + 'static,
+{
+    fn handle(&self, req: &Request) -> Response {
+        self(req)
+    }
+}
+
+/// Wraps a handler: a middleware may inspect or modify the request, call
+/// `next.run(req)` to continue down the chain (or skip it and answer itself),
+/// and then inspect or modify the response.
+pub trait Middleware: Send + Sync + 'static {
+    fn handle(&self, req: &mut Request, next: Next<'_>) -> Response;
+}
+
+impl<F> Middleware for F
+where
+    F: Fn(&mut Request, Next<'_>) -> Response + Send + Sync + 'static,
+{
+    fn handle(&self, req: &mut Request, next: Next<'_>) -> Response {
+        self(req, next)
+    }
+}
+
+/// The rest of the middleware chain, ending with the matched handler.
+pub struct Next<'a> {
+    // router 级别的 middleware 先执行，然后才是 route 自己的
+    global: &'a [Box<dyn Middleware>],
+    route: &'a [Box<dyn Middleware>],
+    handler: &'a dyn Handler,
+}
+
+impl Next<'_> {
+    pub fn run(self, req: &mut Request) -> Response {
+        if let Some((first, rest)) = self.global.split_first() {
+            first.handle(req, Next { global: rest, ..self })
+        } else if let Some((first, rest)) = self.route.split_first() {
+            first.handle(req, Next { route: rest, ..self })
+        } else {
+            self.handler.handle(req)
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    // "{*rest}" 匹配剩下的所有段，只能放在最后
+    CatchAll(String),
+}
+
+// "/users/{id}" => [Literal("users"), Param("id")]
+struct Pattern {
+    segments: Vec<Segment>,
+}
+
+impl Pattern {
+    /// # Panics
+    /// Panics if `pattern` does not start with `/`, has an unterminated `{`
+    /// or a `{*name}` that is not the last segment, since routes are
+    /// registered once at startup.
+    fn parse(pattern: &str) -> Pattern {
+        assert!(pattern.starts_with('/'), "route pattern must start with '/': {pattern}");
+        let segments: Vec<Segment> = split_path(pattern)
+            .map(|s| match s.strip_prefix('{') {
+                Some(name) => {
+                    let name = name.strip_suffix('}').unwrap_or_else(|| panic!("unterminated '{{' in {pattern}"));
+                    match name.strip_prefix('*') {
+                        Some(name) => Segment::CatchAll(name.to_string()),
+                        None => Segment::Param(name.to_string()),
+                    }
+                }
+                None => Segment::Literal(s.to_string()),
+            })
+            .collect();
+        let catch_all = segments.iter().position(|s| matches!(s, Segment::CatchAll(_)));
+        assert!(
+            catch_all.is_none_or(|i| i == segments.len() - 1),
+            "{{*name}} must be the last segment: {pattern}"
+        );
+        Pattern { segments }
+    }
+
+    fn matches(&self, path: &str) -> Option<HashMap<String, String>> {
+        let mut params = HashMap::new();
+        let mut parts = split_path(path);
+        for seg in &self.segments {
+            if let Segment::CatchAll(name) = seg {
+                params.insert(name.clone(), parts.collect::<Vec<_>>().join("/"));
+                return Some(params);
+            }
+            let part = parts.next()?;
+            match seg {
+                Segment::Literal(lit) if lit == part => {}
+                Segment::Literal(_) => return None,
+                Segment::Param(_) if part.is_empty() => return None,
+                Segment::Param(name) => {
+                    params.insert(name.clone(), part.to_string());
+                }
+                Segment::CatchAll(_) => unreachable!(),
+            }
+        }
+        // 段数必须完全一致
+        parts.next().is_none().then_some(params)
+    }
+}
+
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    let path = path.strip_prefix('/').unwrap_or(path);
+    // 根路径 "/" 没有任何段
+    (!path.is_empty()).then(|| path.split('/')).into_iter().flatten()
+}
+
+pub struct Route {
+    method: Method,
+    pattern: Pattern,
+    handler: Box<dyn Handler>,
+    middleware: Vec<Box<dyn Middleware>>,
+}
+
+impl Route {
+    /// Adds a middleware that only runs for this route.
+    pub fn with(&mut self, m: impl Middleware) -> &mut Route {
+        self.middleware.push(Box::new(m));
+        self
+    }
+}
+
+pub struct Router {
+    routes: Vec<Route>,
+    fallback: Box<dyn Handler>,
+    middleware: Vec<Box<dyn Middleware>>,
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router {
+            routes: Vec::new(),
+            fallback: Box::new(|_: &Request| Response::not_found().text("Not Found")),
+            middleware: Vec::new(),
+        }
+    }
+
+    /// Registers `handler` for `method` and a path pattern such as
+    /// `/users/{id}` or `/static/{*path}`; `{name}` segments are exposed
+    /// through `Request::param`, and a trailing `{*name}` captures the rest of
+    /// the path. Routes are tried in registration order.
+    pub fn route(&mut self, method: Method, pattern: &str, handler: impl Handler) -> &mut Route {
+        self.routes.push(Route {
+            method,
+            pattern: Pattern::parse(pattern),
+            handler: Box::new(handler),
+            middleware: Vec::new(),
+        });
+        self.routes.last_mut().unwrap()
+    }
+
+    pub fn get(&mut self, pattern: &str, handler: impl Handler) -> &mut Route {
+        self.route(Method::Get, pattern, handler)
+    }
+
+    pub fn post(&mut self, pattern: &str, handler: impl Handler) -> &mut Route {
+        self.route(Method::Post, pattern, handler)
+    }
+
+    /// Handler used when no route matches the path. Defaults to a plain 404.
+    pub fn fallback(&mut self, handler: impl Handler) -> &mut Router {
+        self.fallback = Box::new(handler);
+        self
+    }
+
+    /// Adds a middleware that runs for every request, including the fallback.
+    pub fn wrap(&mut self, m: impl Middleware) -> &mut Router {
+        self.middleware.push(Box::new(m));
+        self
+    }
+
+    pub fn handle(&self, req: &mut Request) -> Response {
+        let mut allowed = Vec::new();
+        for route in &self.routes {
+            let Some(params) = route.pattern.matches(&req.path) else {
+                continue;
+            };
+            // HEAD 没有单独注册时，复用 GET 的 handler(body 由连接层丢弃)
+            let method_ok = route.method == req.method
+                || (req.method == Method::Head && route.method == Method::Get);
+            if !method_ok {
+                if !allowed.contains(&route.method.as_str()) {
+                    allowed.push(route.method.as_str());
+                }
+                continue;
+            }
+            req.params = params;
+            let next = Next {
+                global: &self.middleware,
+                route: &route.middleware,
+                handler: route.handler.as_ref(),
+            };
+            return next.run(req);
+        }
+
+        if !allowed.is_empty() {
+            // 路径存在但方法不对，应当是 405 而不是 404
+            let allow = allowed.join(", ");
+            let handler = move |_: &Request| {
+                Response::new(StatusCode::METHOD_NOT_ALLOWED)
+                    .header("Allow", allow.clone())
+                    .text("Method Not Allowed")
+            };
+            return Next { global: &self.middleware, route: &[], handler: &handler }.run(req);
+        }
+        Next { global: &self.middleware, route: &[], handler: self.fallback.as_ref() }.run(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::RequestParser;
+
+    fn request(raw: &str) -> Request {
+        let mut parser = RequestParser::new();
+        parser.feed(raw.as_bytes());
+        parser.parse().unwrap().unwrap()
+    }
+
+    fn body(resp: &Response) -> &str {
+        std::str::from_utf8(resp.body.as_bytes().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn path_params_and_fallback() {
+        let mut router = Router::new();
+        router.get("/", |_: &Request| Response::ok().text("index"));
+        router.get("/users/{id}/posts/{post}", |req: &Request| {
+            Response::ok().text(format!("{}-{}", req.param("id").unwrap(), req.param("post").unwrap()))
+        });
+        router.get("/static/{*path}", |req: &Request| Response::ok().text(req.param("path").unwrap().to_string()));
+        router.fallback(|_: &Request| Response::not_found().text("custom 404"));
+
+        assert_eq!(body(&router.handle(&mut request("GET / HTTP/1.1\r\n\r\n"))), "index");
+        let resp = router.handle(&mut request("GET /users/7/posts/42 HTTP/1.1\r\n\r\n"));
+        assert_eq!(body(&resp), "7-42");
+        let resp = router.handle(&mut request("GET /users/7 HTTP/1.1\r\n\r\n"));
+        assert_eq!(resp.status, StatusCode::NOT_FOUND);
+        assert_eq!(body(&resp), "custom 404");
+        let resp = router.handle(&mut request("GET /static/css/site.css HTTP/1.1\r\n\r\n"));
+        assert_eq!(body(&resp), "css/site.css");
+    }
+
+    #[test]
+    fn wrong_method_is_405() {
+        let mut router = Router::new();
+        router.post("/upload", |_: &Request| Response::ok());
+        let resp = router.handle(&mut request("GET /upload HTTP/1.1\r\n\r\n"));
+        assert_eq!(resp.status, StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(resp.headers.get("allow"), Some("POST"));
+    }
+
+    #[test]
+    fn middleware_order() {
+        let mut router = Router::new();
+        router.wrap(|req: &mut Request, next: Next<'_>| {
+            req.headers.append("X-Trace", "global");
+            let resp = next.run(req);
+            resp.header("X-Outer", "1")
+        });
+        router
+            .get("/", |req: &Request| Response::ok().text(req.headers.get_all("x-trace").collect::<Vec<_>>().join(",")))
+            .with(|req: &mut Request, next: Next<'_>| {
+                req.headers.append("X-Trace", "route");
+                next.run(req)
+            });
+        // route 级别的 middleware 不作用于其他路径
+        router.get("/other", |req: &Request| Response::ok().text(req.headers.get_all("x-trace").collect::<Vec<_>>().join(",")));
+
+        let resp = router.handle(&mut request("GET / HTTP/1.1\r\n\r\n"));
+        assert_eq!(body(&resp), "global,route");
+        assert_eq!(resp.headers.get("x-outer"), Some("1"));
+        assert_eq!(body(&router.handle(&mut request("GET /other HTTP/1.1\r\n\r\n"))), "global");
+    }
+}
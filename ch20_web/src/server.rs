@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::conn::{self, ConnConfig};
+use crate::request::{Method, Request};
+use crate::response::{Response, StatusCode};
+use crate::router::{Next, Router};
+use crate::threadpool::{OverflowPolicy, PoolError, ThreadPool};
+
+// accept 出错后等一会再重试
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
+// server 与它的所有连接共享的状态
+struct Shared {
+    local_addrs: Vec<SocketAddr>,
+    shutting_down: AtomicBool,
+    conns: Mutex<HashMap<usize, Arc<Tracked>>>,
+    // 每个客户端 IP 当前的连接数，只在设置了 max_conns_per_ip 时统计
+    per_ip: Mutex<HashMap<IpAddr, usize>>,
+    // conns 变空时通知 run() 里等待的线程
+    drained: Condvar,
+    next_id: AtomicUsize,
+    #[cfg(unix)]
+    signals: Mutex<Option<signal_hook::iterator::Handle>>,
+}
+
+/// A connection as seen by the shutdown logic.
+pub(crate) struct Tracked {
+    // 正在处理请求(已读到请求、响应还没写完)
+    busy: AtomicBool,
+    // try_clone 出来的句柄，只用来 shutdown
+    stream: TcpStream,
+    shared: Arc<Shared>,
+}
+
+impl Tracked {
+    /// Marks the connection as waiting for its next request; returns true if
+    /// the server is shutting down and the connection should be closed instead.
+    pub(crate) fn set_idle(&self) -> bool {
+        self.busy.store(false, Ordering::SeqCst);
+        self.shutting_down()
+    }
+
+    pub(crate) fn set_busy(&self) {
+        self.busy.store(true, Ordering::SeqCst);
+    }
+
+    pub(crate) fn shutting_down(&self) -> bool {
+        self.shared.shutting_down.load(Ordering::SeqCst)
+    }
+}
+
+// 连接处理完(包括 job panic 的情况)时从 conns 里移除
+struct Registration {
+    id: usize,
+    tracked: Arc<Tracked>,
+    _slot: Option<IpSlot>,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let shared = &self.tracked.shared;
+        let mut conns = shared.conns.lock().unwrap();
+        conns.remove(&self.id);
+        if conns.is_empty() {
+            shared.drained.notify_all();
+        }
+    }
+}
+
+// 占用 per_ip 里的一个名额，连接结束时归还
+struct IpSlot {
+    ip: IpAddr,
+    shared: Arc<Shared>,
+}
+
+impl Drop for IpSlot {
+    fn drop(&mut self) {
+        let mut per_ip = self.shared.per_ip.lock().unwrap();
+        if let Some(n) = per_ip.get_mut(&self.ip) {
+            *n -= 1;
+            if *n == 0 {
+                per_ip.remove(&self.ip);
+            }
+        }
+    }
+}
+
+/// Stops a running [`Server`] from any thread.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    shared: Arc<Shared>,
+}
+
+impl ShutdownHandle {
+    /// Makes `Server::run` stop accepting connections and return once the
+    /// in-flight requests are done. Calling it more than once is harmless.
+    pub fn shutdown(&self) {
+        let shared = &self.shared;
+        if shared.shutting_down.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        // accept() 会一直阻塞，自己连一下自己把它唤醒，每个 listener 都要连一次
+        for &addr in &shared.local_addrs {
+            let mut addr = addr;
+            match addr.ip() {
+                IpAddr::V4(ip) if ip.is_unspecified() => addr.set_ip(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+                IpAddr::V6(ip) if ip.is_unspecified() => addr.set_ip(IpAddr::V6(Ipv6Addr::LOCALHOST)),
+                _ => {}
+            }
+            let _ = TcpStream::connect(addr);
+        }
+
+        // 空闲的 keep-alive 连接阻塞在 read 上，关闭读端让它读到 EOF；正在处理的请求不受影响
+        for tracked in shared.conns.lock().unwrap().values() {
+            if !tracked.busy.load(Ordering::SeqCst) {
+                let _ = tracked.stream.shutdown(Shutdown::Read);
+            }
+        }
+    }
+}
+
+/// The threaded HTTP server: one listener, connections served on a `ThreadPool`.
+///
+/// ```no_run
+/// let server = ch20_web::server::Server::bind("127.0.0.1:7878", ch20_web::app()).unwrap();
+/// let handle = server.shutdown_handle();
+/// std::thread::spawn(move || {
+///     std::thread::sleep(std::time::Duration::from_secs(60));
+///     handle.shutdown();
+/// });
+/// server.run();
+/// ```
+pub struct Server {
+    listeners: Vec<TcpListener>,
+    // 到 run() 时才放进 Arc，在那之前还要加上 /metrics
+    router: Router,
+    metrics_path: Option<String>,
+    workers: usize,
+    queue_capacity: usize,
+    overflow: OverflowPolicy,
+    conn_config: ConnConfig,
+    max_conns_per_ip: Option<usize>,
+    shutdown_timeout: Duration,
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<rustls::ServerConfig>>,
+    shared: Arc<Shared>,
+}
+
+impl Server {
+    /// Binds the listener right away, so binding to port 0 and then asking
+    /// `local_addr` gives the ephemeral port before `run` is called.
+    pub fn bind(addr: impl ToSocketAddrs, router: Router) -> io::Result<Server> {
+        Server::bind_all([addr], router)
+    }
+
+    /// Listens on every address in `addrs` (e.g. an IPv4 and an IPv6 one),
+    /// serving all of them with the same router and worker pool.
+    ///
+    /// # Panics
+    /// Panics if `addrs` is empty.
+    pub fn bind_all<A: ToSocketAddrs>(addrs: impl IntoIterator<Item = A>, router: Router) -> io::Result<Server> {
+        let listeners = addrs.into_iter().map(TcpListener::bind).collect::<io::Result<Vec<_>>>()?;
+        assert!(!listeners.is_empty(), "Server::bind_all needs at least one address");
+        let shared = Arc::new(Shared {
+            local_addrs: listeners.iter().map(TcpListener::local_addr).collect::<io::Result<_>>()?,
+            shutting_down: AtomicBool::new(false),
+            conns: Mutex::new(HashMap::new()),
+            per_ip: Mutex::new(HashMap::new()),
+            drained: Condvar::new(),
+            next_id: AtomicUsize::new(0),
+            #[cfg(unix)]
+            signals: Mutex::new(None),
+        });
+        Ok(Server {
+            listeners,
+            router,
+            metrics_path: None,
+            workers: 4,
+            queue_capacity: 64,
+            overflow: OverflowPolicy::Reject,
+            conn_config: ConnConfig::default(),
+            max_conns_per_ip: None,
+            shutdown_timeout: Duration::from_secs(10),
+            #[cfg(feature = "tls")]
+            tls: None,
+            shared,
+        })
+    }
+
+    /// Number of pool workers, 4 by default.
+    pub fn workers(mut self, n: usize) -> Server {
+        self.workers = n;
+        self
+    }
+
+    /// How many accepted connections may wait for a free worker, and what to
+    /// do with the ones beyond that. Defaults to 64 and `Reject`, which
+    /// answers the extra connections with `503 Service Unavailable`.
+    pub fn queue(mut self, capacity: usize, policy: OverflowPolicy) -> Server {
+        self.queue_capacity = capacity;
+        self.overflow = policy;
+        self
+    }
+
+    /// Serves the worker pool's stats in Prometheus text format at `path`
+    /// (GET or HEAD), ahead of any route of the router.
+    pub fn metrics(mut self, path: impl Into<String>) -> Server {
+        self.metrics_path = Some(path.into());
+        self
+    }
+
+    pub fn conn_config(mut self, config: ConnConfig) -> Server {
+        self.conn_config = config;
+        self
+    }
+
+    /// Answers connections beyond `n` open ones from the same IP address with
+    /// `429 Too Many Requests`, so one client cannot take all the workers.
+    /// Unlimited by default.
+    ///
+    /// # Panics
+    /// Panics if `n` is 0.
+    pub fn max_conns_per_ip(mut self, n: usize) -> Server {
+        assert!(n > 0, "max_conns_per_ip must be at least 1");
+        self.max_conns_per_ip = Some(n);
+        self
+    }
+
+    /// Speaks HTTPS instead of plaintext HTTP on every accepted connection,
+    /// see [`tls::load_config`](crate::tls::load_config).
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, config: Arc<rustls::ServerConfig>) -> Server {
+        self.tls = Some(config);
+        self
+    }
+
+    /// How long a shutdown waits for in-flight requests before closing their
+    /// sockets, 10 seconds by default.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Server {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// The first address listened on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.shared.local_addrs[0]
+    }
+
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.shared.local_addrs
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    /// Shuts the server down on the first SIGINT or SIGTERM.
+    #[cfg(unix)]
+    pub fn shutdown_on_signals(&self) -> io::Result<()> {
+        let handle = self.shutdown_handle();
+        *self.shared.signals.lock().unwrap() = Some(on_signals(move || handle.shutdown())?);
+        Ok(())
+    }
+
+    /// Accepts and serves connections until `shutdown` is called, then waits
+    /// for in-flight requests (up to the shutdown timeout) and joins the workers.
+    pub fn run(mut self) {
+        let pool = ThreadPool::bounded(self.workers, self.queue_capacity, self.overflow);
+        let shared = &self.shared;
+        let mut router = std::mem::take(&mut self.router);
+        if let Some(path) = self.metrics_path.take() {
+            // 用 middleware 而不是 route：route 按注册顺序匹配，会被 app 里的 /{*path} 之类抢先
+            let monitor = pool.monitor();
+            router.wrap(move |req: &mut Request, next: Next<'_>| {
+                if req.path != path || !matches!(req.method, Method::Get | Method::Head) {
+                    return next.run(req);
+                }
+                Response::ok()
+                    .header("Content-Type", "text/plain; version=0.0.4; charset=utf-8")
+                    .body(monitor.stats().to_prometheus("ch20_web_pool"))
+            });
+        }
+        let router = Arc::new(router);
+
+        // 第一个 listener 在当前线程上 accept，其余的各开一个线程，连接都交给同一个 pool
+        let listeners = std::mem::take(&mut self.listeners);
+        thread::scope(|s| {
+            for listener in &listeners[1..] {
+                s.spawn(|| self.accept(listener, &pool, &router));
+            }
+            self.accept(&listeners[0], &pool, &router);
+        });
+
+        // 不再接受新连接
+        drop(listeners);
+        drain(shared, self.shutdown_timeout);
+        #[cfg(unix)]
+        if let Some(signals) = shared.signals.lock().unwrap().take() {
+            signals.close();
+        }
+        println!("Waiting for workers to finish.");
+        // ThreadPool::drop 会发送 Terminate 并 join 所有 Worker
+        drop(pool);
+    }
+
+    fn accept(&self, listener: &TcpListener, pool: &ThreadPool, router: &Arc<Router>) {
+        for stream in listener.incoming() {
+            if self.shared.shutting_down.load(Ordering::SeqCst) {
+                break;
+            }
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    println!("accept error: {}", e);
+                    // 比如 fd 用完了(EMFILE)，马上重试只会一直失败，空转占满 CPU 还刷屏
+                    thread::sleep(ACCEPT_ERROR_BACKOFF);
+                    continue;
+                }
+            };
+            let slot = match self.claim_slot(&stream) {
+                Ok(slot) => slot,
+                Err(()) => {
+                    conn::refuse(stream, Response::new(StatusCode::TOO_MANY_REQUESTS).text("too many connections"));
+                    continue;
+                }
+            };
+            let registration = match self.register(&stream, slot) {
+                Ok(r) => r,
+                Err(e) => {
+                    println!("failed to register connection: {}", e);
+                    continue;
+                }
+            };
+            // job 被拒绝时 stream 跟着 job 一起被丢掉了，先留一个句柄用来回 503
+            let spare = stream.try_clone();
+            let router = Arc::clone(router);
+            let config = self.conn_config.clone();
+            #[cfg(feature = "tls")]
+            let tls = self.tls.clone();
+            let job = move || {
+                let tracked = Some(&*registration.tracked);
+                #[cfg(feature = "tls")]
+                let result = match tls {
+                    Some(tls) => crate::tls::accept(&tls, stream).and_then(|s| conn::serve(s, &router, &config, tracked)),
+                    None => conn::serve(stream, &router, &config, tracked),
+                };
+                #[cfg(not(feature = "tls"))]
+                let result = conn::serve(stream, &router, &config, tracked);
+                if let Err(e) = result {
+                    println!("connection error: {}", e);
+                }
+            };
+            if let (Err(PoolError::QueueFull), Ok(stream)) = (pool.try_execute(job), spare) {
+                conn::refuse(stream, Response::new(StatusCode::SERVICE_UNAVAILABLE).text("server is busy"));
+            }
+        }
+    }
+
+    // 对方 IP 的连接数已经到上限时返回 Err；没有上限(或拿不到对方地址)时不占名额
+    fn claim_slot(&self, stream: &TcpStream) -> Result<Option<IpSlot>, ()> {
+        let (Some(max), Ok(peer)) = (self.max_conns_per_ip, stream.peer_addr()) else {
+            return Ok(None);
+        };
+        let mut per_ip = self.shared.per_ip.lock().unwrap();
+        let n = per_ip.entry(peer.ip()).or_insert(0);
+        if *n >= max {
+            return Err(());
+        }
+        *n += 1;
+        Ok(Some(IpSlot { ip: peer.ip(), shared: Arc::clone(&self.shared) }))
+    }
+
+    fn register(&self, stream: &TcpStream, slot: Option<IpSlot>) -> io::Result<Registration> {
+        let id = self.shared.next_id.fetch_add(1, Ordering::Relaxed);
+        let tracked = Arc::new(Tracked {
+            busy: AtomicBool::new(false),
+            stream: stream.try_clone()?,
+            shared: Arc::clone(&self.shared),
+        });
+        self.shared.conns.lock().unwrap().insert(id, Arc::clone(&tracked));
+        // shutdown 可能恰好发生在 accept 之后、登记之前，那时它看不到这个连接
+        if tracked.shutting_down() {
+            let _ = stream.shutdown(Shutdown::Read);
+        }
+        Ok(Registration { id, tracked, _slot: slot })
+    }
+}
+
+// 收到第一个 SIGINT/SIGTERM 时调用 shutdown。
+// run() 结束时要 close 返回的 Handle，forever() 随之返回 None，线程退出
+#[cfg(unix)]
+pub(crate) fn on_signals(shutdown: impl FnOnce() + Send + 'static) -> io::Result<signal_hook::iterator::Handle> {
+    use signal_hook::consts::{SIGINT, SIGTERM};
+    use signal_hook::iterator::Signals;
+
+    let mut signals = Signals::new([SIGINT, SIGTERM])?;
+    let handle = signals.handle();
+    std::thread::spawn(move || {
+        if let Some(sig) = signals.forever().next() {
+            println!("Received signal {}, shutting down.", sig);
+            shutdown();
+        }
+    });
+    Ok(handle)
+}
+
+// 等待正在处理的请求结束，超时后直接关掉剩下的连接
+fn drain(shared: &Shared, timeout: Duration) {
+    let deadline = Instant::now() + timeout;
+    let mut conns = shared.conns.lock().unwrap();
+    while !conns.is_empty() {
+        let now = Instant::now();
+        if now >= deadline {
+            println!("Shutdown timeout, closing {} connection(s).", conns.len());
+            for tracked in conns.values() {
+                let _ = tracked.stream.shutdown(Shutdown::Both);
+            }
+            break;
+        }
+        conns = shared.drained.wait_timeout(conns, deadline - now).unwrap().0;
+    }
+}
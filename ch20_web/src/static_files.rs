@@ -0,0 +1,334 @@
+use std::fs::{self, File, Metadata};
+use std::io::{self, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::request::Request;
+use crate::response::{Body, Response, StatusCode};
+use crate::router::Handler;
+
+/// Serves files below a root directory.
+///
+/// Mount it on a catch-all route, e.g. `router.get("/static/{*path}", ...)`,
+/// in which case the `path` parameter names the file; otherwise (for example
+/// as the router fallback) the whole request path is used.
+pub struct StaticFiles {
+    root: PathBuf,
+    index: String,
+}
+
+impl StaticFiles {
+    pub fn new(root: impl Into<PathBuf>) -> StaticFiles {
+        StaticFiles {
+            root: root.into(),
+            index: "index.html".to_string(),
+        }
+    }
+
+    /// File served for directory requests, `index.html` by default.
+    pub fn index(mut self, name: impl Into<String>) -> StaticFiles {
+        self.index = name.into();
+        self
+    }
+
+    // 把 URL 路径映射到 root 之下，任何 ".." 段都直接拒绝，而不是试图去"规范化"它
+    fn resolve(&self, url_path: &str) -> Result<PathBuf, StatusCode> {
+        let decoded = percent_decode(url_path).ok_or(StatusCode::BAD_REQUEST)?;
+        let mut path = self.root.clone();
+        for seg in decoded.split('/') {
+            match seg {
+                "" | "." => continue,
+                ".." => return Err(StatusCode::FORBIDDEN),
+                s if s.contains(['\\', '\0']) => return Err(StatusCode::FORBIDDEN),
+                s => path.push(s),
+            }
+        }
+        Ok(path)
+    }
+
+    fn serve(&self, req: &Request) -> Result<Response, StatusCode> {
+        let url_path = req.param("path").unwrap_or(&req.path);
+        let path = self.resolve(url_path)?;
+
+        // 即使没有 ".."，root 里的符号链接也可能指向外面，所以再比较一次真实路径
+        let root = self.root.canonicalize().map_err(status_for)?;
+        let mut path = path.canonicalize().map_err(status_for)?;
+        if !path.starts_with(&root) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        let mut meta = fs::metadata(&path).map_err(status_for)?;
+        if meta.is_dir() {
+            // 不以 / 结尾时重定向，否则 index.html 里的相对链接会指错目录
+            if !req.path.ends_with('/') {
+                let mut location = format!("{}/", req.path);
+                if let Some(q) = &req.query {
+                    location.push('?');
+                    location.push_str(q);
+                }
+                return Ok(Response::new(StatusCode::MOVED_PERMANENTLY).header("Location", location));
+            }
+            path.push(&self.index);
+            meta = fs::metadata(&path).map_err(status_for)?;
+        }
+        if !meta.is_file() {
+            return Err(StatusCode::NOT_FOUND);
+        }
+        serve_file(req, &path, &meta)
+    }
+}
+
+impl Handler for StaticFiles {
+    fn handle(&self, req: &Request) -> Response {
+        self.serve(req).unwrap_or_else(|status| Response::new(status).text(status.reason()))
+    }
+}
+
+fn serve_file(req: &Request, path: &Path, meta: &Metadata) -> Result<Response, StatusCode> {
+    let len = meta.len();
+    // HTTP 日期只精确到秒，比较 If-Modified-Since 时也按秒截断
+    let modified = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| UNIX_EPOCH + Duration::from_secs(d.as_secs()));
+    let etag = match modified {
+        Some(m) => format!("\"{:x}-{:x}\"", len, m.duration_since(UNIX_EPOCH).unwrap().as_secs()),
+        None => format!("\"{:x}\"", len),
+    };
+    let last_modified = modified.map(httpdate::fmt_http_date);
+
+    let mut resp = Response::ok()
+        .header("Content-Type", content_type(path))
+        .header("Accept-Ranges", "bytes")
+        .header("ETag", etag.clone());
+    if let Some(lm) = &last_modified {
+        resp = resp.header("Last-Modified", lm.clone());
+    }
+
+    if not_modified(req, &etag, modified) {
+        resp.status = StatusCode::NOT_MODIFIED;
+        return Ok(resp);
+    }
+
+    let range = req
+        .header("Range")
+        .filter(|_| if_range_matches(req, &etag, last_modified.as_deref()));
+    let (start, end) = match range.and_then(|r| parse_range(r, len)) {
+        None => (0, len),
+        Some(Ok((first, last))) => {
+            resp.status = StatusCode::PARTIAL_CONTENT;
+            resp = resp.header("Content-Range", format!("bytes {first}-{last}/{len}"));
+            (first, last + 1)
+        }
+        Some(Err(())) => {
+            return Ok(Response::new(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header("Content-Range", format!("bytes */{len}")));
+        }
+    };
+
+    let mut file = File::open(path).map_err(status_for)?;
+    if start > 0 {
+        file.seek(SeekFrom::Start(start)).map_err(status_for)?;
+    }
+    resp.body = Body::Reader { reader: Box::new(file), len: end - start };
+    Ok(resp)
+}
+
+// If-None-Match 优先于 If-Modified-Since(RFC 9110 13.2.2)
+fn not_modified(req: &Request, etag: &str, modified: Option<SystemTime>) -> bool {
+    if let Some(inm) = req.header("If-None-Match") {
+        // 304 的判断用弱比较，忽略 W/ 前缀
+        let weak = |t: &str| t.trim().trim_start_matches("W/").to_string();
+        return inm.trim() == "*" || inm.split(',').any(|t| weak(t) == weak(etag));
+    }
+    match (req.header("If-Modified-Since").map(httpdate::parse_http_date), modified) {
+        (Some(Ok(since)), Some(modified)) => modified <= since,
+        _ => false,
+    }
+}
+
+// 没有 If-Range，或者文件自那之后没变过，Range 才生效；否则返回整个文件
+fn if_range_matches(req: &Request, etag: &str, last_modified: Option<&str>) -> bool {
+    match req.header("If-Range") {
+        None => true,
+        Some(v) if v.starts_with('"') => v == etag,
+        Some(v) => Some(v) == last_modified,
+    }
+}
+
+/// Parses a single `bytes=` range into an inclusive `(first, last)`.
+///
+/// `None` means the header should be ignored (unknown unit, multiple ranges
+/// or a syntax error) and `Some(Err(()))` that it cannot be satisfied.
+fn parse_range(header: &str, len: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    // 多段 range 需要 multipart/byteranges，这里不支持，RFC 允许直接忽略 Range
+    if spec.contains(',') {
+        return None;
+    }
+    let (first, last) = spec.split_once('-')?;
+    let (first, last) = (first.trim(), last.trim());
+    let range = match (first.is_empty(), last.is_empty()) {
+        (true, true) => return None,
+        // "bytes=-500": 最后 500 个字节
+        (true, false) => {
+            let n: u64 = last.parse().ok()?;
+            if n == 0 || len == 0 {
+                return Some(Err(()));
+            }
+            (len.saturating_sub(n), len - 1)
+        }
+        (false, _) => {
+            let first: u64 = first.parse().ok()?;
+            let last = if last.is_empty() { u64::MAX } else { last.parse().ok()? };
+            if last < first {
+                return None;
+            }
+            if first >= len {
+                return Some(Err(()));
+            }
+            (first, last.min(len - 1))
+        }
+    };
+    Some(Ok(range))
+}
+
+fn status_for(e: io::Error) -> StatusCode {
+    match e.kind() {
+        io::ErrorKind::NotFound | io::ErrorKind::NotADirectory => StatusCode::NOT_FOUND,
+        io::ErrorKind::PermissionDenied => StatusCode::FORBIDDEN,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match ext.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" | "md" => "text/plain; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::RequestParser;
+
+    // 每个测试用自己的临时目录，避免并行执行时互相干扰
+    fn site(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("ch20_web_static_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("hello.txt"), "0123456789").unwrap();
+        fs::write(root.join("docs/index.html"), "<h1>docs</h1>").unwrap();
+        root
+    }
+
+    fn get(files: &StaticFiles, head: &str) -> Response {
+        let mut parser = RequestParser::new();
+        parser.feed(format!("GET {head}\r\n\r\n").as_bytes());
+        files.handle(&parser.parse().unwrap().unwrap())
+    }
+
+    fn body(resp: Response) -> String {
+        String::from_utf8(resp.body.into_bytes().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn serves_files_and_directory_index() {
+        let files = StaticFiles::new(site("index"));
+        let resp = get(&files, "/hello.txt HTTP/1.1");
+        assert_eq!(resp.status, StatusCode::OK);
+        assert_eq!(resp.headers.get("content-type"), Some("text/plain; charset=utf-8"));
+        assert_eq!(body(resp), "0123456789");
+
+        let resp = get(&files, "/docs HTTP/1.1");
+        assert_eq!(resp.status, StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(resp.headers.get("location"), Some("/docs/"));
+        let resp = get(&files, "/docs/ HTTP/1.1");
+        assert_eq!(resp.headers.get("content-type"), Some("text/html; charset=utf-8"));
+        assert_eq!(body(resp), "<h1>docs</h1>");
+    }
+
+    #[test]
+    fn rejects_path_traversal() {
+        let files = StaticFiles::new(site("traversal").join("docs"));
+        assert_eq!(get(&files, "/../hello.txt HTTP/1.1").status, StatusCode::FORBIDDEN);
+        assert_eq!(get(&files, "/%2e%2e/hello.txt HTTP/1.1").status, StatusCode::FORBIDDEN);
+        assert_eq!(get(&files, "/missing.txt HTTP/1.1").status, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn conditional_requests() {
+        let files = StaticFiles::new(site("conditional"));
+        let resp = get(&files, "/hello.txt HTTP/1.1");
+        let etag = resp.headers.get("etag").unwrap().to_string();
+        let lm = resp.headers.get("last-modified").unwrap().to_string();
+
+        let resp = get(&files, &format!("/hello.txt HTTP/1.1\r\nIf-None-Match: {etag}"));
+        assert_eq!(resp.status, StatusCode::NOT_MODIFIED);
+        let resp = get(&files, &format!("/hello.txt HTTP/1.1\r\nIf-Modified-Since: {lm}"));
+        assert_eq!(resp.status, StatusCode::NOT_MODIFIED);
+        let resp = get(&files, "/hello.txt HTTP/1.1\r\nIf-None-Match: \"other\"");
+        assert_eq!(resp.status, StatusCode::OK);
+    }
+
+    #[test]
+    fn range_requests() {
+        let files = StaticFiles::new(site("range"));
+        let resp = get(&files, "/hello.txt HTTP/1.1\r\nRange: bytes=2-5");
+        assert_eq!(resp.status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(resp.headers.get("content-range"), Some("bytes 2-5/10"));
+        assert_eq!(body(resp), "2345");
+
+        assert_eq!(body(get(&files, "/hello.txt HTTP/1.1\r\nRange: bytes=-3")), "789");
+        assert_eq!(body(get(&files, "/hello.txt HTTP/1.1\r\nRange: bytes=7-")), "789");
+        let resp = get(&files, "/hello.txt HTTP/1.1\r\nRange: bytes=10-");
+        assert_eq!(resp.status, StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(resp.headers.get("content-range"), Some("bytes */10"));
+        // If-Range 不匹配时返回整个文件
+        let resp = get(&files, "/hello.txt HTTP/1.1\r\nRange: bytes=2-5\r\nIf-Range: \"stale\"");
+        assert_eq!(resp.status, StatusCode::OK);
+    }
+}
@@ -0,0 +1,124 @@
+use std::num::NonZeroUsize;
+use std::thread;
+use std::time::Duration;
+
+use super::{OverflowPolicy, Scheduler, ThreadPool};
+
+// build() 之后就不会再变，放在 Shared 里给 worker 线程读
+pub(super) struct Config {
+    pub(super) min: usize,
+    pub(super) max: usize,
+    pub(super) keep_alive: Duration,
+    pub(super) name_prefix: String,
+    pub(super) stack_size: Option<usize>,
+    pub(super) scheduler: Scheduler,
+}
+
+/// Configures a [`ThreadPool`] whose size moves between `min` and `max`
+/// workers: it grows when jobs start to queue up and shrinks again when the
+/// extra workers have been idle for `keep_alive`.
+///
+/// ```
+/// use std::time::Duration;
+/// use ch20_web::threadpool::ThreadPool;
+///
+/// let pool = ThreadPool::builder()
+///     .min(2)
+///     .max(16)
+///     .keep_alive(Duration::from_secs(30))
+///     .name_prefix("web-")
+///     .build();
+/// assert_eq!(pool.threads(), 2);
+/// ```
+pub struct Builder {
+    min: usize,
+    max: Option<usize>,
+    keep_alive: Duration,
+    name_prefix: String,
+    stack_size: Option<usize>,
+    scheduler: Scheduler,
+    capacity: Option<usize>,
+    policy: OverflowPolicy,
+}
+
+impl Builder {
+    pub(super) fn new() -> Builder {
+        Builder {
+            min: 1,
+            max: None,
+            keep_alive: Duration::from_secs(60),
+            name_prefix: "worker-".to_string(),
+            stack_size: None,
+            scheduler: Scheduler::Shared,
+            capacity: None,
+            policy: OverflowPolicy::Block,
+        }
+    }
+
+    /// Workers that are kept even when idle, 1 by default.
+    pub fn min(mut self, n: usize) -> Builder {
+        self.min = n;
+        self
+    }
+
+    /// Upper limit on workers; defaults to the number of CPUs (or `min`, if larger).
+    pub fn max(mut self, n: usize) -> Builder {
+        self.max = Some(n);
+        self
+    }
+
+    /// How long a worker above `min` may wait for a job before it exits,
+    /// 60 seconds by default.
+    pub fn keep_alive(mut self, d: Duration) -> Builder {
+        self.keep_alive = d;
+        self
+    }
+
+    /// Threads are named `{prefix}{id}`: `worker-0`, `worker-1`, ... by default.
+    pub fn name_prefix(mut self, prefix: impl Into<String>) -> Builder {
+        self.name_prefix = prefix.into();
+        self
+    }
+
+    /// Stack size of each worker thread in bytes; the std default otherwise.
+    pub fn stack_size(mut self, bytes: usize) -> Builder {
+        self.stack_size = Some(bytes);
+        self
+    }
+
+    /// `Scheduler::Shared` by default. With `WorkStealing`, jobs already moved
+    /// to a worker's local deque no longer count against the queue capacity.
+    pub fn scheduler(mut self, scheduler: Scheduler) -> Builder {
+        self.scheduler = scheduler;
+        self
+    }
+
+    /// Bounds the job queue like [`ThreadPool::bounded`]; unbounded by default.
+    /// # Panics
+    /// Panics if `capacity` is zero.
+    pub fn queue(mut self, capacity: usize, policy: OverflowPolicy) -> Builder {
+        assert!(capacity > 0);
+        self.capacity = Some(capacity);
+        self.policy = policy;
+        self
+    }
+
+    /// # Panics
+    /// Panics if `max` is zero or less than `min`, or if a thread can't be spawned.
+    pub fn build(self) -> ThreadPool {
+        let max = self.max.unwrap_or_else(|| {
+            let cpus = thread::available_parallelism().map_or(4, NonZeroUsize::get);
+            cpus.max(self.min)
+        });
+        assert!(max > 0 && max >= self.min, "need 0 < max >= min, got min {} max {}", self.min, max);
+        let config = Config {
+            min: self.min,
+            max,
+            keep_alive: self.keep_alive,
+            name_prefix: self.name_prefix,
+            stack_size: self.stack_size,
+            scheduler: self.scheduler,
+        };
+        ThreadPool::with_config(config, self.capacity, self.policy)
+    }
+}
@@ -0,0 +1,750 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::{fmt, io};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, TryRecvError};
+use std::time::{Duration, Instant};
+use std::{sync::mpsc, thread};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+
+use crossbeam_deque::{Stealer, Worker as LocalQueue};
+
+mod builder;
+mod par;
+mod queue;
+mod scope;
+mod stats;
+mod stealing;
+
+pub use builder::Builder;
+pub use queue::OverflowPolicy;
+pub use scope::Scope;
+pub use stats::{Monitor, Stats};
+pub use stealing::Scheduler;
+use builder::Config;
+use queue::{JobQueue, Pop};
+use stats::LatencyRing;
+
+// main中不感知Worker，所以保持私有
+struct Worker {
+    id: usize,
+    //thread: thread::JoinHandle<()>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+// 所有 Worker 共享的状态
+struct Shared {
+    queue: JobQueue,
+    config: Config,
+    // 线程会扩容、空闲退出、死掉重启，所以 Worker 不再放在 ThreadPool 里，而是由各个线程自己登记/注销，
+    // ThreadPool drop 时 join 剩下的
+    workers: Mutex<HashMap<usize, Worker>>,
+    // 活着的 worker 线程数。起线程、退出线程之前先在这里占位，保证线程数不超出 [min, max]
+    threads: AtomicUsize,
+    next_id: AtomicUsize,
+    // work-stealing 模式下各个 worker 本地队列的偷取端
+    stealers: RwLock<Vec<(usize, Stealer<Task>)>>,
+    // 以下都是给 stats() 用的
+    busy: AtomicUsize,
+    completed: AtomicU64,
+    latency: LatencyRing,
+    panicked_jobs: AtomicUsize,
+    restarts: AtomicUsize,
+}
+
+// 放在 worker 线程的栈上：线程因为 panic 而退出时，它的 drop 会启动一个替代的 worker
+struct Sentinel {
+    id: usize,
+    shared: Arc<Shared>,
+    // work-stealing 模式下 worker 的本地队列，放在这里是为了线程挂掉时把里面的 job 还回去
+    local: Option<LocalQueue<Task>>,
+}
+
+impl Drop for Sentinel {
+    fn drop(&mut self) {
+        if let Some(local) = &self.local {
+            stealing::unregister(&self.shared, self.id);
+            // 正常退出时本地队列已经是空的
+            self.shared.queue.requeue(std::iter::from_fn(|| local.pop()));
+        }
+        if thread::panicking() {
+            println!("Worker {} died, restarting it.", self.id);
+            self.shared.restarts.fetch_add(1, Ordering::Relaxed);
+            // 替换线程沿用同一个 id，workers 里死掉线程的 JoinHandle 被覆盖掉
+            if let Err(e) = Worker::spawn(self.id, &self.shared) {
+                println!("Failed to restart worker {}: {}", self.id, e);
+                self.shared.threads.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+impl Shared {
+    // job 的 panic 在这里截住，执行它的线程不会因此退出。返回 false 表示 job panic 了
+    fn run(&self, task: Task) -> bool {
+        self.busy.fetch_add(1, Ordering::SeqCst);
+        let result = panic::catch_unwind(AssertUnwindSafe(task.job));
+        self.busy.fetch_sub(1, Ordering::SeqCst);
+        self.completed.fetch_add(1, Ordering::Relaxed);
+        self.latency.record(task.queued_at.elapsed());
+        match result {
+            Ok(()) => true,
+            Err(payload) => {
+                self.panicked_jobs.fetch_add(1, Ordering::Relaxed);
+                // payload 的 drop 本身也可能 panic，放在计数之后，那样的话交给 Sentinel 处理
+                drop(payload);
+                false
+            }
+        }
+    }
+
+    fn workers(&self) -> MutexGuard<'_, HashMap<usize, Worker>> {
+        self.workers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // 线程数还没到 max 就多起一个 worker
+    fn grow(self: &Arc<Self>) {
+        let reserved = self.threads.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+            (n < self.config.max).then_some(n + 1)
+        });
+        // 已经到 max 了
+        if reserved.is_err() {
+            return;
+        }
+        if let Err(e) = self.add_worker() {
+            println!("Failed to spawn worker: {}", e);
+        }
+    }
+
+    // 空闲的 worker 退出前调用，线程数已经是 min 时返回 false，这个 worker 要留下
+    fn retire(&self) -> bool {
+        self.threads
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| (n > self.config.min).then_some(n - 1))
+            .is_ok()
+    }
+
+    // 调用前已经在 threads 里占了位
+    fn add_worker(self: &Arc<Self>) -> io::Result<()> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        Worker::spawn(id, self).inspect_err(|_| {
+            self.threads.fetch_sub(1, Ordering::SeqCst);
+        })
+    }
+}
+
+impl Worker {
+    // 线程起来之后把自己登记到 shared.workers 里
+    fn spawn(id: usize, shared: &Arc<Shared>) -> io::Result<()> {
+        // 用 thread::Builder 代替 thread::spawn，才能设置线程名和栈大小
+        let mut builder = thread::Builder::new().name(format!("{}{}", shared.config.name_prefix, id));
+        if let Some(size) = shared.config.stack_size {
+            builder = builder.stack_size(size);
+        }
+        let thread = {
+            let shared = Arc::clone(shared);
+            builder.spawn(move || Worker::run(id, shared))?
+        };
+        shared.workers().insert(id, Worker { id, thread: Some(thread) });
+        Ok(())
+    }
+
+    fn run(id: usize, shared: Arc<Shared>) {
+        let local = match shared.config.scheduler {
+            Scheduler::Shared => None,
+            Scheduler::WorkStealing => {
+                let local = LocalQueue::new_fifo();
+                stealing::register(&shared, id, local.stealer());
+                Some(local)
+            }
+        };
+        let sentinel = Sentinel { id, shared: Arc::clone(&shared), local };
+        scope::enter_worker(&shared);
+        // min == max 时线程数是固定的，不需要空闲超时
+        let config = &shared.config;
+        let keep_alive = (config.min < config.max).then_some(config.keep_alive);
+        loop {
+            // Mutex 结构体没有unlock方法 ，因为锁的所有权依赖于MutxexGuard<T>的生命周期
+            // 返回的LockGuard在let job 这个语句结束后就会自动 unlock ,以确保 job() 执行时锁是被持有的
+            /* 不能写成这样：
+            while let Ok(job) = receiver.lock().unwrap().recv() {
+                job();
+            }
+            */
+            // 现在换成了 JobQueue，道理一样：pop 返回时锁已经释放了
+            let popped = match &sentinel.local {
+                None => shared.queue.pop(keep_alive),
+                Some(local) => stealing::next_job(&shared, id, local, keep_alive),
+            };
+            match popped {
+                Pop::Job(job) => {
+                    // 每个 job 打一行会让所有 worker 去抢 stdout 的锁，release 构建(benches)里就不打了
+                    if cfg!(debug_assertions) {
+                        println!("Worker {} got a job; executing.", id);
+                    }
+                    if !shared.run(job) {
+                        println!("Worker {} job panicked.", id);
+                    }
+                },
+                Pop::Idle => {
+                    if shared.retire() {
+                        println!("Worker {} was idle for too long, exiting.", id);
+                        // 丢掉自己的 JoinHandle，线程变成 detached 的，反正马上就结束了
+                        shared.workers().remove(&id);
+                        break;
+                    }
+                }
+                // 被唤醒了但全局队列是空的，回到开头去别的 worker 那里偷
+                Pop::Steal => {}
+                // 队列已关闭并且空了，说明 pool 被 drop 了
+                Pop::Closed => {
+                    println!("Worker {} was told to terminate.", id);
+                    break;
+                }
+            }
+        }
+
+        // BAD !!! unlock 逾期，job执行时，Lock仍在持有
+        // while let Ok(job) = receiver.lock().unwrap().recv() {
+        //     println!("Worker {} got a job; executing.", id);
+        //     job();
+        // }
+    }
+}
+
+pub struct ThreadPool {
+    // 为了更多灵活控制，引入中间层Worker，Worker里再包JoinHandle。
+    // Worker 现在都在 shared.workers 里
+    shared: Arc<Shared>,
+    policy: OverflowPolicy,
+}
+
+/* FnOnce: 只能调用一次的闭包 trait，无参数无返回值
+ 'static: 生命周期，表示闭包捕获的数据必须在整个程序运行期间有效
+(P664 not knowing how long thread will run, so it must be static)
+没有 dyn 时，编译器不知道这是 trait object 还是泛型
+dyn 明确表示这是一个 trait object（运行时多态
+
+Send - 标记 trait（不需要 dyn）：
+    这是标记 trait（marker trait），只是给类型添加约束
+    不定义具体行为，只是告诉编译器"这个类型可以跨线程传递"
+    是对主 trait 的额外约束
+*/
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+// 入队时记下时间，统计 job 从提交到做完的延迟
+struct Task {
+    job: Job,
+    queued_at: Instant,
+}
+
+/*  这样写会报多种描述的错误：
+impl Trait` in type aliases is unstable (因为 impl Trait 代表一个具体的但未命名的类型 而类型别名需要明确的类型)
+unconstrained opaque type
+`Job` must be used in combination with a concrete type within the same crate
+
+type Job = Box<impl FnOnce() + Send + 'static>;
+*/
+
+impl ThreadPool {
+    /// Creates a new ThreadPool with the specified number of threads.
+    /// # Panics
+    /// Panics if `size` is zero.
+    pub fn new(sz: usize) -> ThreadPool {
+        assert!(sz > 0);
+        ThreadPool::builder().min(sz).max(sz).build()
+    }
+
+    /// Creates a pool whose queue holds at most `capacity` waiting jobs;
+    /// `policy` decides what happens to jobs submitted beyond that.
+    /// # Panics
+    /// Panics if `sz` or `capacity` is zero.
+    pub fn bounded(sz: usize, capacity: usize, policy: OverflowPolicy) -> ThreadPool {
+        assert!(sz > 0);
+        ThreadPool::builder().min(sz).max(sz).queue(capacity, policy).build()
+    }
+
+    /// A pool that grows and shrinks between `min` and `max` workers, see [`Builder`].
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+
+    fn with_config(config: Config, capacity: Option<usize>, policy: OverflowPolicy) -> ThreadPool {
+        let min = config.min;
+        // 最早用的是 mpsc::channel，多个 worker 共享 Arc<Mutex<Receiver>>。
+        // Arc允许多线程共用，Mutex保证只有一个线程从接收端接收任务；JobQueue 内部也是同样的 Mutex
+        let shared = Arc::new(Shared {
+            queue: JobQueue::new(capacity),
+            config,
+            workers: Mutex::new(HashMap::new()),
+            threads: AtomicUsize::new(min),
+            next_id: AtomicUsize::new(0),
+            stealers: RwLock::new(Vec::new()),
+            busy: AtomicUsize::new(0),
+            completed: AtomicU64::new(0),
+            latency: LatencyRing::new(),
+            panicked_jobs: AtomicUsize::new(0),
+            restarts: AtomicUsize::new(0),
+        });
+
+        for _ in 0..min {
+            shared.add_worker().expect("failed to spawn worker thread");
+        }
+        ThreadPool {
+            shared,
+            policy,
+        }
+    }
+    pub fn new_wrong(sz: usize) {
+        assert!(sz > 0);
+        //let (sender, receiver) = mpsc::channel();
+
+        // 如果下方不直接用receiver，上面的语句会报：cannot infer type of the type parameter `T` declared on the function `channel`
+        // 为了让此语句不注释掉，需要加::<T>
+        let (sender, receiver) = mpsc::channel::<Job>();
+
+        let mut workers: Vec<Receiver<Job>> = Vec::with_capacity(sz);
+        for id in 0..sz {
+            // Report: move occurs because `receiver` has type `std::sync::mpsc::Receiver<Message>`, which does not implement the `Copy` trait
+            //workers.push(receiver);
+
+            // Report: expected `Receiver<Message>`, found `&Receiver<_>`
+            //workers.push(&receiver);
+
+            // method not found in `std::sync::mpsc::Receiver<_>`
+            // workers.push(receiver.clone());
+        }
+    }
+
+    // FnOnce() 的() 表示没有参数，也没有返回值，不可省()
+    // If omit 'static, report: the parameter type `F` must be valid for the static lifetime,so that the type `F` will meet its required lifetime bounds
+    //   use 'static because we don't know how long the thread will take to execute (Eng ver P654)
+    //   需要借用栈上的数据时用 scope()，它保证返回前所有 job 都已结束
+    pub fn execute<F>(&self, f: F)
+        where F: FnOnce() + Send + 'static {
+        let job = Box::new(f);
+        let job2 = Box::new(12);
+        //Message::NewJob(job2); //  expected an `FnOnce()` closure, found `{integer}
+        // 队列满了并且 policy 是 Reject 时 job 只能丢弃，没必要让调用方跟着 panic
+        if let Err(e) = self.push(job) {
+            println!("{}, job dropped.", e);
+        }
+    }
+
+    /// Like `execute`, but reports a job refused by the `Reject` policy
+    /// instead of dropping it silently.
+    pub fn try_execute<F>(&self, f: F) -> Result<(), PoolError>
+        where F: FnOnce() + Send + 'static {
+        self.push(Box::new(f))
+    }
+
+    fn push(&self, job: Job) -> Result<(), PoolError> {
+        self.push_with(job, self.policy)
+    }
+
+    fn push_with(&self, job: Job, policy: OverflowPolicy) -> Result<(), PoolError> {
+        let config = &self.shared.config;
+        // 新 job 找不到空闲的 worker，说明队列开始积压了，扩容
+        if config.min < config.max && self.shared.queue.backed_up() {
+            self.shared.grow();
+        }
+        let task = Task { job, queued_at: Instant::now() };
+        match self.shared.queue.push(task, policy) {
+            Ok(()) => Ok(()),
+            Err(task) if policy == OverflowPolicy::CallerRuns => {
+                // 调用方自己去执行，顺便拖慢了它提交新 job 的速度，这就是背压
+                self.shared.run(task);
+                Ok(())
+            }
+            Err(_) => Err(PoolError::QueueFull),
+        }
+    }
+
+    /// Runs `f` on the pool and returns a handle to its result.
+    ///
+    /// A panic inside `f` is caught and reported through the handle as
+    /// `JobError::Panicked` instead of being lost with the worker thread.
+    /// A job refused or dropped by the overflow policy reports `JobError::Cancelled`.
+    pub fn submit<F, T>(&self, f: F) -> JobHandle<T>
+        where F: FnOnce() -> T + Send + 'static, T: Send + 'static {
+        // 容量为 1 就够了，job 只会发送一次结果，send 永远不会阻塞
+        let (tx, rx) = mpsc::sync_channel(1);
+        let shared = Arc::clone(&self.shared);
+        self.execute(move || {
+            // AssertUnwindSafe: panic 之后 f 捕获的数据不会再被使用(只剩 panic 信息)，所以是安全的
+            let result = panic::catch_unwind(AssertUnwindSafe(f));
+            if result.is_err() {
+                shared.panicked_jobs.fetch_add(1, Ordering::Relaxed);
+            }
+            // 调用方可能已经丢掉了 JobHandle，发送失败也无所谓
+            let _ = tx.send(result);
+        });
+        JobHandle { rx }
+    }
+
+    /// Queue depth, worker states, job counts and latency right now.
+    pub fn stats(&self) -> Stats {
+        self.shared.stats()
+    }
+
+    /// A handle for reading `stats()` from other threads, which keeps
+    /// working (frozen at the last values) after the pool is dropped.
+    pub fn monitor(&self) -> Monitor {
+        Monitor {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    /// Number of worker threads currently alive.
+    pub fn threads(&self) -> usize {
+        self.shared.threads.load(Ordering::SeqCst)
+    }
+
+    /// Number of jobs that panicked so far.
+    pub fn panicked_jobs(&self) -> usize {
+        self.shared.panicked_jobs.load(Ordering::Relaxed)
+    }
+
+    /// Number of worker threads that died and were replaced.
+    pub fn restarts(&self) -> usize {
+        self.shared.restarts.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PoolError {
+    // 队列已满，policy 是 Reject
+    QueueFull,
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolError::QueueFull => write!(f, "job queue is full"),
+        }
+    }
+}
+
+impl std::error::Error for PoolError {}
+
+#[derive(Debug)]
+pub enum JobError {
+    // job 里发生了 panic，附带 panic 信息
+    Panicked(String),
+    // job 没有执行就被丢弃了(例如队列满了被 overflow policy 丢掉)
+    Cancelled,
+    // wait_timeout 到时间了 job 还没完成，之后仍然可以继续等
+    Timeout,
+}
+
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobError::Panicked(msg) => write!(f, "job panicked: {}", msg),
+            JobError::Cancelled => write!(f, "job was cancelled"),
+            JobError::Timeout => write!(f, "timed out waiting for job"),
+        }
+    }
+}
+
+impl std::error::Error for JobError {}
+
+/// The result of a job handed to [`ThreadPool::submit`]; it can be taken once.
+pub struct JobHandle<T> {
+    rx: mpsc::Receiver<thread::Result<T>>,
+}
+
+impl<T> JobHandle<T> {
+    /// Blocks until the job has finished.
+    pub fn wait(self) -> Result<T, JobError> {
+        match self.rx.recv() {
+            Ok(result) => job_result(result),
+            Err(_) => Err(JobError::Cancelled),
+        }
+    }
+
+    /// Returns `None` while the job is still queued or running.
+    pub fn try_wait(&self) -> Option<Result<T, JobError>> {
+        match self.rx.try_recv() {
+            Ok(result) => Some(job_result(result)),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(JobError::Cancelled)),
+        }
+    }
+
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<T, JobError> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(result) => job_result(result),
+            Err(RecvTimeoutError::Timeout) => Err(JobError::Timeout),
+            Err(RecvTimeoutError::Disconnected) => Err(JobError::Cancelled),
+        }
+    }
+}
+
+fn job_result<T>(result: thread::Result<T>) -> Result<T, JobError> {
+    result.map_err(|payload| JobError::Panicked(panic_message(payload.as_ref())))
+}
+
+// panic!("...") 的 payload 是 &str，带格式化参数时是 String
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "<non-string panic payload>".to_string()
+    }
+}
+
+impl Drop for ThreadPool {
+    // support graceful shutdown
+    fn drop(&mut self) {
+        println!("Sending terminate message to all workers.");
+        // 以前是给每个 worker 发一条 Terminate 消息；现在关闭队列，worker 做完排在前面的 job 后退出
+        self.shared.queue.close();
+        // 被替换的、刚退出的线程还会改动 workers，所以每次取一批出来 join，直到取不到为止
+        loop {
+            let workers: Vec<Worker> = self.shared.workers().drain().map(|(_, w)| w).collect();
+            if workers.is_empty() {
+                break;
+            }
+            for mut worker in workers {
+                println!("Shutting down worker {}", worker.id);
+                // 如果worker 不是 Option<> 类型，这样写会报错,join方法要求取得调用者的所有权
+                // Error: cannot move out of `worker.thread` which is behind a mutable reference
+
+                // Eng ver P670 says: move occurs because `worker.thread` has type `JoinHandle<()>`, which does not implement the `Copy` trait
+                // worker.thread.join().unwrap();
+
+                // immitate what's been used in request_review
+                if let Some(thread) = worker.thread.take() {
+                    // 因 panic 退出的线程 join 会返回 Err，它已经被替换过了，忽略即可
+                    let _ = thread.join();
+                }
+
+                /*
+                Eng ver介绍了新的方法, 更推荐
+                fn drop(&mut self) {
+                    for worker in self.workers.drain(..) {
+                        worker.thread.join().unwrap();
+                    }
+                 */
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn submit_returns_the_result() {
+        let pool = ThreadPool::new(2);
+        let handles: Vec<_> = (0..8).map(|i| pool.submit(move || i * i)).collect();
+        let results: Vec<i32> = handles.into_iter().map(|h| h.wait().unwrap()).collect();
+        assert_eq!(results, vec![0, 1, 4, 9, 16, 25, 36, 49]);
+    }
+
+    #[test]
+    fn panic_is_reported_and_pool_keeps_working() {
+        let pool = ThreadPool::new(1);
+        let handle = pool.submit(|| -> i32 { panic!("boom {}", 42) });
+        match handle.wait() {
+            Err(JobError::Panicked(msg)) => assert_eq!(msg, "boom 42"),
+            other => panic!("unexpected {:?}", other),
+        }
+        // panic 被 catch_unwind 截住了，唯一的 worker 还活着
+        assert_eq!(pool.submit(|| "still alive").wait().unwrap(), "still alive");
+    }
+
+    #[test]
+    fn poll_and_timeout() {
+        let pool = ThreadPool::new(1);
+        let (tx, rx) = mpsc::channel::<()>();
+        let handle = pool.submit(move || {
+            rx.recv().unwrap();
+            "done"
+        });
+        assert!(handle.try_wait().is_none());
+        assert!(matches!(handle.wait_timeout(Duration::from_millis(20)), Err(JobError::Timeout)));
+        tx.send(()).unwrap();
+        assert_eq!(handle.wait_timeout(Duration::from_secs(5)).unwrap(), "done");
+    }
+
+    // 一个 worker、队列容量 1：先用一个 job 占住 worker，再排一个 job 把队列填满
+    fn full_pool(policy: OverflowPolicy) -> (ThreadPool, mpsc::Sender<()>, JobHandle<&'static str>) {
+        let pool = ThreadPool::bounded(1, 1, policy);
+        let (tx, rx) = mpsc::channel::<()>();
+        let (started_tx, started_rx) = mpsc::channel();
+        pool.execute(move || {
+            started_tx.send(()).unwrap();
+            rx.recv().unwrap();
+        });
+        started_rx.recv().unwrap();
+        let queued = pool.submit(|| "queued");
+        (pool, tx, queued)
+    }
+
+    #[test]
+    fn reject_when_full() {
+        let (pool, tx, queued) = full_pool(OverflowPolicy::Reject);
+        assert_eq!(pool.try_execute(|| ()), Err(PoolError::QueueFull));
+        assert!(matches!(pool.submit(|| 1).wait(), Err(JobError::Cancelled)));
+        tx.send(()).unwrap();
+        assert_eq!(queued.wait().unwrap(), "queued");
+    }
+
+    #[test]
+    fn drop_oldest_when_full() {
+        let (pool, tx, queued) = full_pool(OverflowPolicy::DropOldest);
+        let newest = pool.submit(|| "newest");
+        tx.send(()).unwrap();
+        assert!(matches!(queued.wait(), Err(JobError::Cancelled)));
+        assert_eq!(newest.wait().unwrap(), "newest");
+    }
+
+    #[test]
+    fn caller_runs_when_full() {
+        let (pool, tx, _queued) = full_pool(OverflowPolicy::CallerRuns);
+        let caller = thread::current().id();
+        let ran_on = pool.submit(|| thread::current().id());
+        assert_eq!(ran_on.try_wait().unwrap().unwrap(), caller);
+        tx.send(()).unwrap();
+    }
+
+    #[test]
+    fn block_waits_for_room() {
+        let (pool, tx, queued) = full_pool(OverflowPolicy::Block);
+        let pool = Arc::new(pool);
+        let submitter = {
+            let pool = Arc::clone(&pool);
+            thread::spawn(move || pool.submit(|| "late").wait())
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert!(!submitter.is_finished());
+        tx.send(()).unwrap();
+        assert_eq!(queued.wait().unwrap(), "queued");
+        assert_eq!(submitter.join().unwrap().unwrap(), "late");
+    }
+
+    #[test]
+    fn grows_under_load_and_shrinks_when_idle() {
+        let pool = ThreadPool::builder()
+            .min(1)
+            .max(3)
+            .keep_alive(Duration::from_millis(50))
+            .build();
+        assert_eq!(pool.threads(), 1);
+
+        let (tx, rx) = mpsc::channel::<()>();
+        let rx = Arc::new(Mutex::new(rx));
+        let handles: Vec<_> = (0..3)
+            .map(|_| {
+                let rx = Arc::clone(&rx);
+                pool.submit(move || rx.lock().unwrap().recv().unwrap())
+            })
+            .collect();
+        // 三个 job 同时阻塞着，只有扩到 3 个线程才能都跑起来
+        assert_eq!(pool.threads(), 3);
+        for _ in 0..3 {
+            tx.send(()).unwrap();
+        }
+        for h in handles {
+            h.wait_timeout(Duration::from_secs(5)).unwrap();
+        }
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while pool.threads() > 1 && std::time::Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(pool.threads(), 1);
+        // 剩下的 worker 还能干活
+        assert_eq!(pool.submit(|| 7).wait().unwrap(), 7);
+    }
+
+    #[test]
+    fn threads_are_named() {
+        let pool = ThreadPool::builder().min(1).max(1).name_prefix("web-").stack_size(256 * 1024).build();
+        let name = pool.submit(|| thread::current().name().map(str::to_string)).wait().unwrap();
+        assert_eq!(name.as_deref(), Some("web-0"));
+    }
+
+    #[test]
+    fn work_stealing_runs_every_job() {
+        let pool = ThreadPool::builder().min(4).max(4).scheduler(Scheduler::WorkStealing).build();
+        let handles: Vec<_> = (0..1000u64).map(|i| pool.submit(move || i)).collect();
+        let sum: u64 = handles.into_iter().map(|h| h.wait().unwrap()).sum();
+        assert_eq!(sum, 999 * 1000 / 2);
+    }
+
+    #[test]
+    fn blocked_workers_jobs_get_stolen() {
+        let pool = ThreadPool::builder().min(2).max(2).scheduler(Scheduler::WorkStealing).build();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let (done_tx, done_rx) = mpsc::channel();
+        pool.execute(move || release_rx.recv().unwrap());
+        for i in 0..9 {
+            let done_tx = done_tx.clone();
+            pool.execute(move || done_tx.send(i).unwrap());
+        }
+        // 被阻塞的 worker 可能一次拿了好几个 job 放在本地队列里，只有被另一个 worker 偷走才能在放行前做完
+        for _ in 0..9 {
+            done_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+        release_tx.send(()).unwrap();
+    }
+
+    #[test]
+    fn stats_snapshot() {
+        let pool = ThreadPool::new(1);
+        let (tx, rx) = mpsc::channel::<()>();
+        let (started_tx, started_rx) = mpsc::channel();
+        pool.execute(move || {
+            started_tx.send(()).unwrap();
+            rx.recv().unwrap();
+        });
+        started_rx.recv().unwrap();
+        let handles: Vec<_> = (0..2).map(|i| pool.submit(move || i)).collect();
+
+        let stats = pool.stats();
+        assert_eq!((stats.queued, stats.busy, stats.idle, stats.completed), (2, 1, 0, 0));
+
+        tx.send(()).unwrap();
+        for h in handles {
+            h.wait().unwrap();
+        }
+        // job 返回之后才计数，submit 的结果可能比计数先到
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while pool.stats().completed < 3 && std::time::Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let stats = pool.monitor().stats();
+        assert_eq!((stats.queued, stats.busy, stats.idle, stats.completed), (0, 0, 1, 3));
+        assert!(stats.latency_p50 > Duration::ZERO && stats.latency_p50 <= stats.latency_p99);
+
+        let text = stats.to_prometheus("pool");
+        assert!(text.contains("# TYPE pool_queued_jobs gauge\npool_queued_jobs 0\n"));
+        assert!(text.contains("pool_workers{state=\"idle\"} 1\n"));
+        assert!(text.contains("pool_job_latency_seconds_count 3\n"));
+    }
+
+    // payload 在 drop 时 panic：catch_unwind 截住了 job 的 panic，但 worker 丢弃 payload 时线程还是会挂掉
+    struct PanicOnDrop;
+
+    impl Drop for PanicOnDrop {
+        fn drop(&mut self) {
+            panic!("payload dropped");
+        }
+    }
+
+    #[test]
+    fn dead_worker_is_restarted() {
+        let pool = ThreadPool::new(1);
+        pool.execute(|| panic::panic_any(PanicOnDrop));
+        // 只有一个 worker，它能处理这个 job 就说明替换线程已经起来了
+        assert_eq!(pool.submit(|| 1 + 1).wait_timeout(Duration::from_secs(5)).unwrap(), 2);
+        assert_eq!(pool.panicked_jobs(), 1);
+        assert_eq!(pool.restarts(), 1);
+    }
+}
@@ -0,0 +1,118 @@
+use super::ThreadPool;
+
+// 每个 worker 分几块：块太大时有的 worker 做完了只能干等，块太小调度开销又大
+const CHUNKS_PER_WORKER: usize = 4;
+
+impl ThreadPool {
+    fn chunk_size(&self, len: usize) -> usize {
+        // 按 max 而不是当前线程数切，弹性 pool 才会在积压时扩容
+        let chunks = self.shared.config.max.max(1) * CHUNKS_PER_WORKER;
+        len.div_ceil(chunks).max(1)
+    }
+
+    /// Applies `f` to every item on the pool; results come back in input order.
+    ///
+    /// ```
+    /// let pool = ch20_web::threadpool::ThreadPool::new(4);
+    /// let squares = pool.par_map(&[1, 2, 3, 4, 5], |x| x * x);
+    /// assert_eq!(squares, vec![1, 4, 9, 16, 25]);
+    /// ```
+    pub fn par_map<T, R, F>(&self, items: &[T], f: F) -> Vec<R>
+    where
+        T: Sync,
+        R: Send,
+        F: Fn(&T) -> R + Sync,
+    {
+        let size = self.chunk_size(items.len());
+        // 每块的结果先放进自己的 Vec，最后按顺序拼起来
+        let mut parts: Vec<Vec<R>> = items.chunks(size).map(|_| Vec::new()).collect();
+        let f = &f;
+        self.scope(|s| {
+            for (chunk, part) in items.chunks(size).zip(parts.iter_mut()) {
+                s.spawn(move || *part = chunk.iter().map(f).collect());
+            }
+        });
+        parts.into_iter().flatten().collect()
+    }
+
+    /// Calls `f` on every item on the pool, in no particular order.
+    pub fn par_for_each<T, F>(&self, items: &[T], f: F)
+    where
+        T: Sync,
+        F: Fn(&T) + Sync,
+    {
+        let size = self.chunk_size(items.len());
+        let f = &f;
+        self.scope(|s| {
+            for chunk in items.chunks(size) {
+                s.spawn(move || chunk.iter().for_each(f));
+            }
+        });
+    }
+
+    /// Maps every item with `map` and combines the results with `op`, like
+    /// `items.iter().map(map).reduce(op)`; `None` for an empty slice.
+    ///
+    /// `op` must be associative, as each chunk is reduced separately, but it
+    /// need not be commutative: the chunk results are combined in order.
+    ///
+    /// ```
+    /// let pool = ch20_web::threadpool::ThreadPool::new(4);
+    /// let words = ["a", "b", "c", "d"];
+    /// let joined = pool.par_reduce(&words, |w| w.to_string(), |a, b| a + &b);
+    /// assert_eq!(joined.as_deref(), Some("abcd"));
+    /// ```
+    pub fn par_reduce<T, R, M, Op>(&self, items: &[T], map: M, op: Op) -> Option<R>
+    where
+        T: Sync,
+        R: Send,
+        M: Fn(&T) -> R + Sync,
+        Op: Fn(R, R) -> R + Sync,
+    {
+        let size = self.chunk_size(items.len());
+        let mut parts: Vec<Option<R>> = items.chunks(size).map(|_| None).collect();
+        let (map, op) = (&map, &op);
+        self.scope(|s| {
+            for (chunk, part) in items.chunks(size).zip(parts.iter_mut()) {
+                s.spawn(move || *part = chunk.iter().map(map).reduce(op));
+            }
+        });
+        parts.into_iter().flatten().reduce(op)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn map_keeps_input_order() {
+        let pool = ThreadPool::new(3);
+        let items: Vec<usize> = (0..1000).collect();
+        let doubled = pool.par_map(&items, |x| x * 2);
+        assert_eq!(doubled, items.iter().map(|x| x * 2).collect::<Vec<_>>());
+        assert!(pool.par_map(&[] as &[usize], |x| *x).is_empty());
+    }
+
+    #[test]
+    fn for_each_visits_every_item() {
+        let pool = ThreadPool::new(3);
+        let sum = AtomicUsize::new(0);
+        pool.par_for_each(&(1..=100).collect::<Vec<usize>>(), |x| {
+            sum.fetch_add(*x, Ordering::Relaxed);
+        });
+        assert_eq!(sum.into_inner(), 5050);
+    }
+
+    #[test]
+    fn reduce_combines_chunks_in_order() {
+        let pool = ThreadPool::new(3);
+        let items: Vec<u32> = (0..200).collect();
+        // 字符串拼接满足结合律但不满足交换律，顺序错了结果就不对
+        let joined = pool.par_reduce(&items, |x| x.to_string(), |a, b| a + "," + &b);
+        let expected = items.iter().map(u32::to_string).collect::<Vec<_>>().join(",");
+        assert_eq!(joined, Some(expected));
+        assert_eq!(pool.par_reduce(&[] as &[u32], |x| *x, |a, b| a + b), None);
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use crossbeam_deque::Worker as LocalQueue;
+
+use super::Task;
+
+// work-stealing 模式下一次最多从全局队列拿走多少个 job
+const MAX_BATCH: usize = 32;
+
+/// What happens to a new job when a bounded queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait until a worker takes a job off the queue.
+    Block,
+    /// Refuse the job; `try_execute` returns `PoolError::QueueFull`.
+    Reject,
+    /// Throw away the job that has been waiting the longest to make room.
+    DropOldest,
+    /// Run the job right away on the thread that submitted it.
+    CallerRuns,
+}
+
+pub(super) enum Pop {
+    Job(Task),
+    // 等了 keep_alive 还没有 job
+    Idle,
+    // 队列已关闭并且空了
+    Closed,
+    // work-stealing 模式：被唤醒了但全局队列是空的，去别的 worker 那里偷
+    Steal,
+}
+
+struct State {
+    jobs: VecDeque<Task>,
+    // 正在 pop 里等 job 的 worker 数
+    idle: usize,
+    // ThreadPool 被 drop 了，worker 做完剩下的 job 就退出
+    closed: bool,
+}
+
+// 代替 mpsc::channel：需要容量上限，DropOldest 还要能从队头拿走 job，channel 都做不到
+pub(super) struct JobQueue {
+    state: Mutex<State>,
+    // None 表示不限长度
+    capacity: Option<usize>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+impl JobQueue {
+    pub(super) fn new(capacity: Option<usize>) -> JobQueue {
+        JobQueue {
+            state: Mutex::new(State {
+                jobs: VecDeque::new(),
+                idle: 0,
+                closed: false,
+            }),
+            capacity,
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+        }
+    }
+
+    // 锁被毒化(poisoned)只说明别的线程持锁时 panic 了，队列本身没有被破坏，可以继续用。
+    // 若这里 unwrap，一个 worker 出事会让其他所有 worker 跟着 panic，整个 pool 悄无声息地停掉
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn is_full(&self, state: &State) -> bool {
+        self.capacity.is_some_and(|cap| state.jobs.len() >= cap)
+    }
+
+    /// Queues `job`, or hands it back if the queue is full and `policy` is
+    /// `Reject` or `CallerRuns`.
+    pub(super) fn push(&self, job: Task, policy: OverflowPolicy) -> Result<(), Task> {
+        let mut state = self.lock();
+        let mut evicted = None;
+        if self.is_full(&state) {
+            match policy {
+                OverflowPolicy::Block => {
+                    while self.is_full(&state) && !state.closed {
+                        state = self.not_full.wait(state).unwrap_or_else(PoisonError::into_inner);
+                    }
+                }
+                OverflowPolicy::DropOldest => evicted = state.jobs.pop_front(),
+                OverflowPolicy::Reject | OverflowPolicy::CallerRuns => return Err(job),
+            }
+        }
+        state.jobs.push_back(job);
+        drop(state);
+        self.not_empty.notify_one();
+        // 被挤掉的 job 可能持有 TcpStream 之类的资源，在锁外面 drop
+        if evicted.is_some() {
+            println!("Job queue is full, dropped the oldest job.");
+        }
+        Ok(())
+    }
+
+    /// Jobs waiting in the shared queue.
+    pub(super) fn len(&self) -> usize {
+        self.lock().jobs.len()
+    }
+
+    /// True if a job pushed now would not find an idle worker to pick it up.
+    pub(super) fn backed_up(&self) -> bool {
+        let state = self.lock();
+        state.jobs.len() >= state.idle
+    }
+
+    /// Blocks until a job is available, or for at most `keep_alive` if given.
+    pub(super) fn pop(&self, keep_alive: Option<Duration>) -> Pop {
+        self.wait_for_job(keep_alive, false, |jobs| jobs.pop_front())
+    }
+
+    /// Takes the next job if there is one, without waiting.
+    pub(super) fn try_pop(&self) -> Option<Task> {
+        let job = self.lock().jobs.pop_front();
+        if job.is_some() {
+            self.not_full.notify_one();
+        }
+        job
+    }
+
+    /// Like `pop`, but takes up to half of the queued jobs at once: the first
+    /// one is returned, the rest go to `local` where siblings can steal them.
+    pub(super) fn pop_batch(&self, local: &LocalQueue<Task>, keep_alive: Option<Duration>) -> Pop {
+        self.wait_for_job(keep_alive, true, |jobs| {
+            let n = jobs.len().div_ceil(2).min(MAX_BATCH);
+            let mut batch = jobs.drain(..n);
+            let first = batch.next();
+            batch.for_each(|job| local.push(job));
+            first
+        })
+    }
+
+    fn wait_for_job(
+        &self,
+        keep_alive: Option<Duration>,
+        steal_on_wake: bool,
+        mut take: impl FnMut(&mut VecDeque<Task>) -> Option<Task>,
+    ) -> Pop {
+        let deadline = keep_alive.map(|d| Instant::now() + d);
+        let mut state = self.lock();
+        loop {
+            let queued = state.jobs.len();
+            if let Some(job) = take(&mut state.jobs) {
+                let taken = queued - state.jobs.len();
+                if taken > 1 && state.idle > 0 {
+                    // 本地队列里有多拿的 job，叫醒一个空闲的 worker 来偷
+                    self.not_empty.notify_one();
+                }
+                for _ in 0..taken {
+                    self.not_full.notify_one();
+                }
+                return Pop::Job(job);
+            }
+            if state.closed {
+                return Pop::Closed;
+            }
+            state.idle += 1;
+            let timed_out = match deadline {
+                None => {
+                    state = self.not_empty.wait(state).unwrap_or_else(PoisonError::into_inner);
+                    false
+                }
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    let (guard, result) =
+                        self.not_empty.wait_timeout(state, timeout).unwrap_or_else(PoisonError::into_inner);
+                    state = guard;
+                    result.timed_out()
+                }
+            };
+            state.idle -= 1;
+            // 超时的同时可能刚好有 job 进来，先把 job 取走
+            if state.jobs.is_empty() && !state.closed {
+                if timed_out {
+                    return Pop::Idle;
+                }
+                if steal_on_wake {
+                    return Pop::Steal;
+                }
+            }
+        }
+    }
+
+    /// Puts jobs back without looking at the capacity, e.g. the local queue of
+    /// a worker that died.
+    pub(super) fn requeue(&self, jobs: impl IntoIterator<Item = Task>) {
+        let mut state = self.lock();
+        let before = state.jobs.len();
+        state.jobs.extend(jobs);
+        let added = state.jobs.len() - before;
+        drop(state);
+        for _ in 0..added {
+            self.not_empty.notify_one();
+        }
+    }
+
+    pub(super) fn close(&self) {
+        self.lock().closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+}
@@ -0,0 +1,295 @@
+use std::any::Any;
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use super::{Job, OverflowPolicy, Shared, ThreadPool, stealing};
+
+thread_local! {
+    // 当前线程是哪个 pool 的 worker，scope 在 worker 上等待时要帮忙执行 job
+    static WORKER_OF: Cell<*const Shared> = const { Cell::new(ptr::null()) };
+}
+
+pub(super) fn enter_worker(shared: &Arc<Shared>) {
+    WORKER_OF.with(|w| w.set(Arc::as_ptr(shared)));
+}
+
+fn is_worker_of(shared: &Arc<Shared>) -> bool {
+    WORKER_OF.with(|w| ptr::eq(w.get(), Arc::as_ptr(shared)))
+}
+
+#[derive(Default)]
+struct State {
+    // 还没结束(执行完或被丢弃)的 job 数
+    pending: usize,
+    // 第一个 panic 的 job 的 payload，等所有 job 结束后重新抛出
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+#[derive(Default)]
+struct ScopeState {
+    state: Mutex<State>,
+    done: Condvar,
+}
+
+impl ScopeState {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Spawns jobs that may borrow from the stack of the [`ThreadPool::scope`]
+/// caller; see there.
+pub struct Scope<'scope, 'env: 'scope> {
+    pool: &'scope ThreadPool,
+    state: Arc<ScopeState>,
+    // 和 std::thread::Scope 一样，让两个生命周期都是 invariant 的
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+// 不管 job 是在 worker 上执行的，还是没执行就被 drop 了，都会在最后把 pending 减一，scope 靠它判断能不能返回
+struct Pending<F: FnOnce()> {
+    f: Option<F>,
+    state: Arc<ScopeState>,
+    shared: Arc<Shared>,
+}
+
+impl<F: FnOnce()> Pending<F> {
+    fn run(mut self) {
+        self.run_once();
+    }
+
+    fn run_once(&mut self) {
+        let Some(f) = self.f.take() else { return };
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(f)) {
+            self.shared.panicked_jobs.fetch_add(1, Ordering::Relaxed);
+            let mut state = self.state.lock();
+            if state.panic.is_none() {
+                state.panic = Some(payload);
+            }
+        }
+    }
+}
+
+impl<F: FnOnce()> Drop for Pending<F> {
+    fn drop(&mut self) {
+        // 还没执行就被 drop 了：别的 job 用 DropOldest 把它挤出了队列，或者 pool 关闭时队列被清空。
+        // scope 保证每个 job 都执行完，所以就在 drop 它的线程上执行(相当于 CallerRuns)。
+        // 执行完 f 也就 drop 掉了，f 借用了 scope 外面的数据，必须在 pending 减一之前结束
+        self.run_once();
+        let mut state = self.state.lock();
+        state.pending -= 1;
+        if state.pending == 0 {
+            self.state.done.notify_all();
+        }
+    }
+}
+
+impl<'scope> Scope<'scope, '_> {
+    /// Runs `f` on the pool. Unlike `execute`, `f` only has to live as long
+    /// as the scope. A full queue never rejects or drops a scoped job: with
+    /// the `Reject` and `DropOldest` policies it runs on the calling thread instead.
+    pub fn spawn<F>(&'scope self, f: F)
+    where
+        F: FnOnce() + Send + 'scope,
+    {
+        self.state.lock().pending += 1;
+        let pending = Pending {
+            f: Some(f),
+            state: Arc::clone(&self.state),
+            shared: Arc::clone(&self.pool.shared),
+        };
+        let job: Box<dyn FnOnce() + Send + 'scope> = Box::new(move || pending.run());
+        // SAFETY: 队列里的 job 必须是 'static 的，这里只是骗过类型检查。
+        // scope() 返回前一定会等到 pending 归零，而 Pending 的 drop 在 f 被 drop 之后才减一，
+        // 所以 f 借用的数据在 f 还活着的时候都是有效的
+        let job: Job = unsafe { mem::transmute::<Box<dyn FnOnce() + Send + 'scope>, Job>(job) };
+        // DropOldest 会挤掉排在前面的 job(可能是别人 execute 的)，所以和 Reject 一样换成 CallerRuns
+        let policy = match self.pool.policy {
+            OverflowPolicy::Reject | OverflowPolicy::DropOldest => OverflowPolicy::CallerRuns,
+            policy => policy,
+        };
+        // 只有 Reject 会返回 Err，上面已经换掉了
+        let _ = self.pool.push_with(job, policy);
+    }
+
+    fn wait(&self) {
+        // 在 worker 线程上干等的话，若 scope 的 job 还排在队列里，而 worker 都像这样在等，就死锁了
+        let help = is_worker_of(&self.pool.shared);
+        let mut state = self.state.lock();
+        while state.pending > 0 {
+            if !help {
+                state = self.state.done.wait(state).unwrap_or_else(PoisonError::into_inner);
+                continue;
+            }
+            drop(state);
+            let shared = &self.pool.shared;
+            // work-stealing 模式下 job 可能在某个 worker 的本地队列里，那个 worker 也许正在这样等着，所以也要去偷
+            match shared.queue.try_pop().or_else(|| stealing::steal_any(shared)) {
+                Some(task) => {
+                    shared.run(task);
+                }
+                None => {
+                    // 剩下的 job 正在别的 worker 上执行
+                    let state = self.state.lock();
+                    let _ = self.state.done.wait_timeout(state, Duration::from_millis(1));
+                }
+            }
+            state = self.state.lock();
+        }
+    }
+}
+
+impl ThreadPool {
+    /// Like `std::thread::scope`, but the jobs run on the pool's workers:
+    /// jobs spawned with [`Scope::spawn`] can borrow local variables, because
+    /// `scope` only returns after every one of them has finished.
+    ///
+    /// If `f` or any job panics, the panic is resumed once all jobs are done.
+    ///
+    /// ```
+    /// let pool = ch20_web::threadpool::ThreadPool::new(4);
+    /// let mut counts = [0; 4];
+    /// pool.scope(|s| {
+    ///     for (i, c) in counts.iter_mut().enumerate() {
+    ///         s.spawn(move || *c = i * 10);
+    ///     }
+    /// });
+    /// assert_eq!(counts, [0, 10, 20, 30]);
+    /// ```
+    pub fn scope<'env, F, R>(&self, f: F) -> R
+    where
+        F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> R,
+    {
+        let scope = Scope {
+            pool: self,
+            state: Arc::new(ScopeState::default()),
+            scope: PhantomData,
+            env: PhantomData,
+        };
+        // f panic 了也要等 job 都结束，不然它们借用的数据就失效了
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+        scope.wait();
+
+        let mut state = scope.state.lock();
+        let job_panic = state.panic.take();
+        drop(state);
+        let r = result.unwrap_or_else(|payload| panic::resume_unwind(payload));
+        if let Some(payload) = job_panic {
+            panic::resume_unwind(payload);
+        }
+        r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::threadpool::Scheduler;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[test]
+    fn jobs_borrow_the_callers_stack() {
+        let pool = ThreadPool::new(3);
+        let data: Vec<u64> = (1..=100).collect();
+        let mut sums = [0u64; 4];
+        pool.scope(|s| {
+            for (chunk, sum) in data.chunks(25).zip(sums.iter_mut()) {
+                s.spawn(move || *sum = chunk.iter().sum());
+            }
+        });
+        assert_eq!(sums.iter().sum::<u64>(), 5050);
+    }
+
+    #[test]
+    fn panic_is_resumed_after_every_job_finished() {
+        let pool = ThreadPool::new(2);
+        let finished = AtomicBool::new(false);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.scope(|s| {
+                s.spawn(|| panic!("scoped boom"));
+                s.spawn(|| {
+                    thread::sleep(Duration::from_millis(50));
+                    finished.store(true, Ordering::SeqCst);
+                });
+            })
+        }));
+        let payload = result.unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"scoped boom"));
+        assert!(finished.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn nested_scope_on_a_single_worker() {
+        for scheduler in [Scheduler::Shared, Scheduler::WorkStealing] {
+            let pool = ThreadPool::builder().min(1).max(1).scheduler(scheduler).build();
+            let mut out = [0; 3];
+            pool.scope(|s| {
+                s.spawn(|| {
+                    // 唯一的 worker 在这里等，内层的 job 只能由它自己帮忙执行
+                    pool.scope(|inner| {
+                        for (i, o) in out.iter_mut().enumerate() {
+                            inner.spawn(move || *o = i + 1);
+                        }
+                    });
+                });
+            });
+            assert_eq!(out, [1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn full_queue_runs_scoped_job_on_caller() {
+        let pool = ThreadPool::bounded(1, 1, OverflowPolicy::Reject);
+        let (tx, rx) = mpsc::channel::<()>();
+        let (started_tx, started_rx) = mpsc::channel();
+        pool.execute(move || {
+            started_tx.send(()).unwrap();
+            rx.recv().unwrap();
+        });
+        started_rx.recv().unwrap();
+        pool.execute(|| ());
+
+        let caller = thread::current().id();
+        let mut ran_on = None;
+        pool.scope(|s| s.spawn(|| ran_on = Some(thread::current().id())));
+        assert_eq!(ran_on, Some(caller));
+        tx.send(()).unwrap();
+    }
+
+    #[test]
+    fn drop_oldest_never_loses_scoped_jobs() {
+        let pool = ThreadPool::bounded(1, 1, OverflowPolicy::DropOldest);
+        let (tx, rx) = mpsc::channel::<()>();
+        let (started_tx, started_rx) = mpsc::channel();
+        pool.execute(move || {
+            started_tx.send(()).unwrap();
+            rx.recv().unwrap();
+        });
+        started_rx.recv().unwrap();
+
+        let mut out = [0; 3];
+        pool.scope(|s| {
+            let (first, rest) = out.split_first_mut().unwrap();
+            // 队列是空的，这个 job 排进队列；下面的 execute 把它挤掉，它就在调用方上执行
+            s.spawn(move || *first = 1);
+            pool.execute(|| ());
+            // 队列满了，直接在调用方上执行，不会挤掉上面那个 execute 的 job
+            for (i, o) in rest.iter_mut().enumerate() {
+                s.spawn(move || *o = i + 2);
+            }
+        });
+        assert_eq!(out, [1, 2, 3]);
+        assert_eq!(pool.par_map(&[1, 2, 3, 4], |x| x * 10), vec![10, 20, 30, 40]);
+        assert_eq!(pool.stats().queued, 1);
+        tx.send(()).unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+use std::fmt::Write as _;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use super::Shared;
+
+// 只保留最近这么多个 job 的延迟
+const LATENCY_SAMPLES: usize = 1024;
+
+// 环形缓冲区，记录最近 LATENCY_SAMPLES 个 job 从提交到做完的耗时(纳秒)。
+// 不加锁：每个 worker 用 fetch_add 抢一个槽位写进去，读的时候碰到正在被覆盖的槽也无所谓，统计而已
+pub(super) struct LatencyRing {
+    slots: Box<[AtomicU64]>,
+    next: AtomicUsize,
+    total_nanos: AtomicU64,
+}
+
+impl LatencyRing {
+    pub(super) fn new() -> LatencyRing {
+        LatencyRing {
+            slots: (0..LATENCY_SAMPLES).map(|_| AtomicU64::new(0)).collect(),
+            next: AtomicUsize::new(0),
+            total_nanos: AtomicU64::new(0),
+        }
+    }
+
+    pub(super) fn record(&self, latency: Duration) {
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        let i = self.next.fetch_add(1, Ordering::Relaxed) % LATENCY_SAMPLES;
+        self.slots[i].store(nanos, Ordering::Relaxed);
+        self.total_nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    // p50, p90, p99；还没有样本时都是 0
+    fn percentiles(&self) -> [Duration; 3] {
+        let filled = self.next.load(Ordering::Relaxed).min(LATENCY_SAMPLES);
+        let mut samples: Vec<u64> = self.slots[..filled].iter().map(|s| s.load(Ordering::Relaxed)).collect();
+        if samples.is_empty() {
+            return [Duration::ZERO; 3];
+        }
+        samples.sort_unstable();
+        // nearest-rank 法
+        let at = |q: f64| {
+            let rank = (q * samples.len() as f64).ceil() as usize;
+            Duration::from_nanos(samples[rank.clamp(1, samples.len()) - 1])
+        };
+        [at(0.5), at(0.9), at(0.99)]
+    }
+}
+
+/// A point-in-time view of a [`ThreadPool`](super::ThreadPool).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    /// Jobs waiting to run, including those in work-stealing local deques.
+    pub queued: usize,
+    /// Workers running a job right now.
+    pub busy: usize,
+    /// Workers waiting for a job.
+    pub idle: usize,
+    /// Jobs finished since the pool was created, the panicked ones included.
+    pub completed: u64,
+    pub panicked: usize,
+    pub restarts: usize,
+    /// Time from submission to completion, over the last 1024 jobs.
+    pub latency_p50: Duration,
+    pub latency_p90: Duration,
+    pub latency_p99: Duration,
+    /// Summed latency of all completed jobs.
+    pub latency_total: Duration,
+}
+
+impl Stats {
+    /// Renders the stats in the Prometheus text format, every metric name
+    /// starting with `prefix`.
+    pub fn to_prometheus(&self, prefix: &str) -> String {
+        let mut out = String::new();
+        // 往 String 里 write! 不会失败
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(&str, String)]| {
+            let _ = writeln!(out, "# HELP {prefix}_{name} {help}");
+            let _ = writeln!(out, "# TYPE {prefix}_{name} {kind}");
+            for (suffix, value) in samples {
+                let _ = writeln!(out, "{prefix}_{name}{suffix} {value}");
+            }
+        };
+        metric("queued_jobs", "gauge", "Jobs waiting for a worker.", &[("", self.queued.to_string())]);
+        metric(
+            "workers",
+            "gauge",
+            "Worker threads by state.",
+            &[
+                ("{state=\"busy\"}", self.busy.to_string()),
+                ("{state=\"idle\"}", self.idle.to_string()),
+            ],
+        );
+        metric("jobs_completed_total", "counter", "Jobs finished.", &[("", self.completed.to_string())]);
+        metric("jobs_panicked_total", "counter", "Jobs that panicked.", &[("", self.panicked.to_string())]);
+        metric("worker_restarts_total", "counter", "Workers replaced after dying.", &[("", self.restarts.to_string())]);
+        metric(
+            "job_latency_seconds",
+            "summary",
+            "Time from submission to completion.",
+            &[
+                ("{quantile=\"0.5\"}", self.latency_p50.as_secs_f64().to_string()),
+                ("{quantile=\"0.9\"}", self.latency_p90.as_secs_f64().to_string()),
+                ("{quantile=\"0.99\"}", self.latency_p99.as_secs_f64().to_string()),
+                ("_sum", self.latency_total.as_secs_f64().to_string()),
+                ("_count", self.completed.to_string()),
+            ],
+        );
+        out
+    }
+}
+
+impl Shared {
+    pub(super) fn stats(&self) -> Stats {
+        let threads = self.threads.load(Ordering::SeqCst);
+        // CallerRuns 时调用方线程也算 busy，可能比 threads 还多
+        let busy = self.busy.load(Ordering::SeqCst);
+        let [latency_p50, latency_p90, latency_p99] = self.latency.percentiles();
+        Stats {
+            queued: self.queue.len() + super::stealing::local_len(self),
+            busy,
+            idle: threads.saturating_sub(busy),
+            completed: self.completed.load(Ordering::Relaxed),
+            panicked: self.panicked_jobs.load(Ordering::Relaxed),
+            restarts: self.restarts.load(Ordering::Relaxed),
+            latency_p50,
+            latency_p90,
+            latency_p99,
+            latency_total: Duration::from_nanos(self.latency.total_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// A cloneable handle that reads a pool's [`Stats`] from anywhere, e.g. a
+/// `/metrics` handler, without holding on to the pool itself.
+#[derive(Clone)]
+pub struct Monitor {
+    pub(super) shared: Arc<Shared>,
+}
+
+impl Monitor {
+    pub fn stats(&self) -> Stats {
+        self.shared.stats()
+    }
+}
@@ -0,0 +1,78 @@
+use std::sync::{PoisonError, RwLockReadGuard};
+use std::time::Duration;
+
+use crossbeam_deque::{Steal, Stealer, Worker as LocalQueue};
+
+use super::queue::Pop;
+use super::{Shared, Task};
+
+/// How workers pick up jobs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheduler {
+    /// Every job goes through one mutex-protected queue shared by all workers.
+    Shared,
+    /// Each worker keeps a local deque it refills from the shared queue in
+    /// batches, and steals from its siblings when both are empty. Cheaper
+    /// for lots of short jobs, since workers rarely touch the shared lock.
+    WorkStealing,
+}
+
+// 同一个 id 的 worker 死掉后会被替换，所以先注销再登记
+pub(super) fn register(shared: &Shared, id: usize, stealer: Stealer<Task>) {
+    let mut stealers = shared.stealers.write().unwrap_or_else(PoisonError::into_inner);
+    stealers.retain(|(i, _)| *i != id);
+    stealers.push((id, stealer));
+}
+
+pub(super) fn unregister(shared: &Shared, id: usize) {
+    let mut stealers = shared.stealers.write().unwrap_or_else(PoisonError::into_inner);
+    stealers.retain(|(i, _)| *i != id);
+}
+
+/// Jobs sitting in the local deques.
+pub(super) fn local_len(shared: &Shared) -> usize {
+    stealers(shared).iter().map(|(_, s)| s.len()).sum()
+}
+
+fn stealers(shared: &Shared) -> RwLockReadGuard<'_, Vec<(usize, Stealer<Task>)>> {
+    shared.stealers.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Next job for worker `id`: its own deque first, then the siblings', and
+/// only then the shared queue, which may block.
+pub(super) fn next_job(shared: &Shared, id: usize, local: &LocalQueue<Task>, keep_alive: Option<Duration>) -> Pop {
+    if let Some(job) = local.pop() {
+        return Pop::Job(job);
+    }
+    if let Some(job) = steal(shared, id, local) {
+        return Pop::Job(job);
+    }
+    shared.queue.pop_batch(local, keep_alive)
+}
+
+/// Takes one job from any local deque, for threads that have none of their own.
+pub(super) fn steal_any(shared: &Shared) -> Option<Task> {
+    stealers(shared).iter().find_map(|(_, stealer)| {
+        // Retry 表示和别的线程撞上了，那边还有 job，再试一次
+        std::iter::repeat_with(|| stealer.steal()).find(|s| !s.is_retry())?.success()
+    })
+}
+
+fn steal(shared: &Shared, id: usize, local: &LocalQueue<Task>) -> Option<Task> {
+    let stealers = stealers(shared);
+    let n = stealers.len();
+    // 从自己后面那个开始偷，免得所有 worker 都盯着第一个
+    let me = stealers.iter().position(|(i, _)| *i == id).unwrap_or(0);
+    for k in 1..n {
+        let (_, stealer) = &stealers[(me + k) % n];
+        loop {
+            // 偷一半过来，第一个直接返回
+            match stealer.steal_batch_and_pop(local) {
+                Steal::Success(job) => return Some(job),
+                Steal::Empty => break,
+                Steal::Retry => continue,
+            }
+        }
+    }
+    None
+}
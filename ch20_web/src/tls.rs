@@ -0,0 +1,60 @@
+// HTTPS：accept 到的 TcpStream 外面包一层 rustls 的 ServerConnection，HTTP 部分(conn::serve)不变。
+// 握手在第一次 read 时才进行，所以也受 idle_timeout 限制
+use std::io::{self, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::Arc;
+
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+
+use crate::conn::Transport;
+
+pub type TlsStream = StreamOwned<ServerConnection, TcpStream>;
+
+impl Transport for TlsStream {
+    fn socket(&self) -> &TcpStream {
+        &self.sock
+    }
+
+    fn finish(&mut self) {
+        self.conn.send_close_notify();
+        let _ = self.flush();
+    }
+}
+
+/// Loads a certificate chain and its private key from PEM files.
+pub fn load_config(cert: impl AsRef<Path>, key: impl AsRef<Path>) -> io::Result<Arc<ServerConfig>> {
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(invalid)?;
+    let key = PrivateKeyDer::from_pem_file(key).map_err(invalid)?;
+    config(certs, key)
+}
+
+/// Same as [`load_config`] with the PEM text already in memory.
+pub fn config_from_pem(cert: &[u8], key: &[u8]) -> io::Result<Arc<ServerConfig>> {
+    let certs = CertificateDer::pem_slice_iter(cert)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(invalid)?;
+    let key = PrivateKeyDer::from_pem_slice(key).map_err(invalid)?;
+    config(certs, key)
+}
+
+fn config(certs: Vec<CertificateDer<'static>>, key: PrivateKeyDer<'static>) -> io::Result<Arc<ServerConfig>> {
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(invalid)?;
+    Ok(Arc::new(config))
+}
+
+pub(crate) fn accept(config: &Arc<ServerConfig>, stream: TcpStream) -> io::Result<TlsStream> {
+    let conn = ServerConnection::new(Arc::clone(config)).map_err(invalid)?;
+    Ok(StreamOwned::new(conn, stream))
+}
+
+fn invalid(e: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
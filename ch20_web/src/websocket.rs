@@ -0,0 +1,547 @@
+// WebSocket(RFC 6455)。握手就是一个普通的 GET，handler 返回 101 之后，
+// conn::serve 把连接交给 upgrade 时传入的闭包，闭包仍然在同一个 ThreadPool worker 上运行，
+// 所以每个打开的 WebSocket 会一直占着一个 worker，pool 的大小要留出余量。
+// 读超时默认是 idle_timeout，空闲的连接 recv 会返回错误，不会无限期占着 worker
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use sha1::{Digest, Sha1};
+
+use crate::conn::Transport;
+use crate::request::{Method, Request, Version};
+use crate::response::{Response, StatusCode, Upgrade};
+
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+/// Status codes of a close frame (RFC 6455 7.4.1).
+pub mod close_code {
+    pub const NORMAL: u16 = 1000;
+    pub const GOING_AWAY: u16 = 1001;
+    pub const PROTOCOL_ERROR: u16 = 1002;
+    pub const UNSUPPORTED_DATA: u16 = 1003;
+    pub const INVALID_PAYLOAD: u16 = 1007;
+    pub const POLICY_VIOLATION: u16 = 1008;
+    pub const MESSAGE_TOO_BIG: u16 = 1009;
+    pub const INTERNAL_ERROR: u16 = 1011;
+
+    // 1005/1006/1015 只在本地表示"没有状态码"、"异常断开"、"TLS 失败"，不能出现在帧里
+    pub(crate) fn is_valid(code: u16) -> bool {
+        matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseFrame {
+    pub code: u16,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    /// `None` when the peer's close frame carried no status code.
+    Close(Option<CloseFrame>),
+}
+
+#[derive(Debug)]
+pub enum WsError {
+    Io(io::Error),
+    /// The peer broke the protocol; a close frame with `code` has been sent.
+    Protocol { code: u16, reason: &'static str },
+    /// The closing handshake is over, nothing more can be sent or received.
+    Closed,
+}
+
+impl fmt::Display for WsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WsError::Io(e) => write!(f, "i/o error: {e}"),
+            WsError::Protocol { code, reason } => write!(f, "protocol error ({code}): {reason}"),
+            WsError::Closed => f.write_str("websocket is closed"),
+        }
+    }
+}
+
+impl std::error::Error for WsError {}
+
+impl From<io::Error> for WsError {
+    fn from(e: io::Error) -> WsError {
+        WsError::Io(e)
+    }
+}
+
+fn protocol(code: u16, reason: &'static str) -> WsError {
+    WsError::Protocol { code, reason }
+}
+
+/// Answers a WebSocket handshake request. On success the response is a
+/// `101 Switching Protocols`, and once it is written `handler` gets the
+/// connection, on the same pool worker that served the request:
+///
+/// ```no_run
+/// use ch20_web::request::Request;
+/// use ch20_web::websocket::{self, Message};
+///
+/// let mut router = ch20_web::router::Router::new();
+/// router.get("/echo", |req: &Request| {
+///     websocket::upgrade(req, |ws| {
+///         while let Ok(msg) = ws.recv() {
+///             let echo = matches!(msg, Message::Text(_) | Message::Binary(_));
+///             if echo && ws.send(msg).is_err() {
+///                 break;
+///             }
+///         }
+///     })
+/// });
+/// ```
+///
+/// A request that is not a valid handshake gets `400 Bad Request`, or
+/// `426 Upgrade Required` when only the protocol version is wrong.
+pub fn upgrade(req: &Request, handler: impl FnOnce(&mut WebSocket<'_>) + Send + 'static) -> Response {
+    let accept = match accept_key(req) {
+        Ok(accept) => accept,
+        Err(resp) => return resp,
+    };
+    let mut resp = Response::new(StatusCode::SWITCHING_PROTOCOLS)
+        .header("Upgrade", "websocket")
+        .header("Connection", "Upgrade")
+        .header("Sec-WebSocket-Accept", accept);
+    resp.upgrade = Some(Upgrade(Box::new(move |stream, buffered| {
+        let mut ws = WebSocket::new(stream, buffered);
+        handler(&mut ws);
+        let _ = ws.finish();
+    })));
+    resp
+}
+
+// 检查握手请求(RFC 6455 4.2.1)，算出 Sec-WebSocket-Accept
+fn accept_key(req: &Request) -> Result<String, Response> {
+    let bad = |why: &str| Response::new(StatusCode::BAD_REQUEST).text(format!("bad websocket handshake: {why}"));
+    if req.method != Method::Get || req.version != Version::Http11 {
+        return Err(bad("must be an HTTP/1.1 GET"));
+    }
+    if !req.headers.has_token("Upgrade", "websocket") || !req.headers.has_token("Connection", "Upgrade") {
+        return Err(bad("missing Upgrade: websocket"));
+    }
+    if req.header("Sec-WebSocket-Version").map(str::trim) != Some("13") {
+        return Err(Response::new(StatusCode::UPGRADE_REQUIRED)
+            .header("Sec-WebSocket-Version", "13")
+            .text("unsupported websocket version"));
+    }
+    let key = req.header("Sec-WebSocket-Key").map(str::trim).unwrap_or_default();
+    if BASE64.decode(key).map_or(true, |k| k.len() != 16) {
+        return Err(bad("invalid Sec-WebSocket-Key"));
+    }
+    Ok(accept_for(key))
+}
+
+fn accept_for(key: &str) -> String {
+    let mut sha = Sha1::new();
+    sha.update(key.as_bytes());
+    sha.update(GUID.as_bytes());
+    BASE64.encode(sha.finalize())
+}
+
+#[derive(Debug)]
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+// 从 buf 开头解析一个完整的帧，不够就返回 None，buf 不变。
+// 客户端发来的帧必须带掩码，解析时顺便去掉
+fn parse_frame(buf: &mut Vec<u8>, max_len: usize) -> Result<Option<Frame>, WsError> {
+    if buf.len() < 2 {
+        return Ok(None);
+    }
+    let (b0, b1) = (buf[0], buf[1]);
+    if b0 & 0x70 != 0 {
+        // 没有协商任何扩展，RSV 位必须是 0
+        return Err(protocol(close_code::PROTOCOL_ERROR, "reserved bits set"));
+    }
+    let fin = b0 & 0x80 != 0;
+    let opcode = b0 & 0x0F;
+    if b1 & 0x80 == 0 {
+        return Err(protocol(close_code::PROTOCOL_ERROR, "client frame not masked"));
+    }
+    let (len, mut pos) = match b1 & 0x7F {
+        126 if buf.len() >= 4 => (u16::from_be_bytes([buf[2], buf[3]]) as u64, 4),
+        127 if buf.len() >= 10 => (u64::from_be_bytes(buf[2..10].try_into().unwrap()), 10),
+        126 | 127 => return Ok(None),
+        n => (n as u64, 2),
+    };
+    if opcode >= 0x8 && (len > 125 || !fin) {
+        return Err(protocol(close_code::PROTOCOL_ERROR, "control frames must be short and unfragmented"));
+    }
+    if len > max_len as u64 {
+        return Err(protocol(close_code::MESSAGE_TOO_BIG, "frame too large"));
+    }
+    let len = len as usize;
+    if buf.len() < pos + 4 + len {
+        return Ok(None);
+    }
+    let mask: [u8; 4] = buf[pos..pos + 4].try_into().unwrap();
+    pos += 4;
+    let mut payload: Vec<u8> = buf.drain(..pos + len).skip(pos).collect();
+    apply_mask(&mut payload, mask);
+    Ok(Some(Frame { fin, opcode, payload }))
+}
+
+fn apply_mask(data: &mut [u8], mask: [u8; 4]) {
+    for (i, b) in data.iter_mut().enumerate() {
+        *b ^= mask[i % 4];
+    }
+}
+
+// server 发出的帧不带掩码，也从不分片
+fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(payload.len() + 10);
+    out.push(0x80 | opcode);
+    match payload.len() {
+        n if n < 126 => out.push(n as u8),
+        n if n <= u16::MAX as usize => {
+            out.push(126);
+            out.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        n => {
+            out.push(127);
+            out.extend_from_slice(&(n as u64).to_be_bytes());
+        }
+    }
+    out.extend_from_slice(payload);
+    out
+}
+
+fn close_payload(frame: &Option<CloseFrame>) -> Vec<u8> {
+    match frame {
+        None => Vec::new(),
+        Some(f) => {
+            let mut p = f.code.to_be_bytes().to_vec();
+            p.extend_from_slice(f.reason.as_bytes());
+            p
+        }
+    }
+}
+
+fn parse_close(payload: &[u8]) -> Result<Option<CloseFrame>, WsError> {
+    match payload {
+        [] => Ok(None),
+        [_] => Err(protocol(close_code::PROTOCOL_ERROR, "close payload of one byte")),
+        [hi, lo, reason @ ..] => {
+            let code = u16::from_be_bytes([*hi, *lo]);
+            if !close_code::is_valid(code) {
+                return Err(protocol(close_code::PROTOCOL_ERROR, "invalid close code"));
+            }
+            let reason = String::from_utf8(reason.to_vec())
+                .map_err(|_| protocol(close_code::INVALID_PAYLOAD, "close reason is not utf-8"))?;
+            Ok(Some(CloseFrame { code, reason }))
+        }
+    }
+}
+
+/// The server side of an established WebSocket connection.
+///
+/// `recv` answers pings and the peer's close frame by itself. When the
+/// handler returns, a close frame is sent if none was yet, and the
+/// connection is closed.
+pub struct WebSocket<'a> {
+    stream: &'a mut dyn Transport,
+    // 已经读到、还没解析成帧的数据
+    buf: Vec<u8>,
+    // 分片消息：第一帧的 opcode 和目前收到的数据
+    fragments: Option<(u8, Vec<u8>)>,
+    max_message: usize,
+    sent_close: bool,
+    received_close: bool,
+}
+
+impl<'a> WebSocket<'a> {
+    fn new(stream: &'a mut dyn Transport, buffered: Vec<u8>) -> WebSocket<'a> {
+        WebSocket {
+            stream,
+            buf: buffered,
+            fragments: None,
+            max_message: 16 * 1024 * 1024,
+            sent_close: false,
+            received_close: false,
+        }
+    }
+
+    /// Largest message (after reassembling fragments) accepted, 16 MiB by
+    /// default; bigger ones close the connection with 1009.
+    pub fn set_max_message_size(&mut self, bytes: usize) {
+        self.max_message = bytes;
+    }
+
+    /// Makes `recv` fail with a `WouldBlock`/`TimedOut` error after waiting
+    /// this long, so a handler can push messages between reads. A partly
+    /// received frame is kept for the next `recv`.
+    ///
+    /// Defaults to the server's `idle_timeout`. `None` waits forever and
+    /// ties up the pool worker for as long as the client stays silent.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.socket().set_read_timeout(timeout)
+    }
+
+    /// Reads the next message, reassembling fragmented ones.
+    ///
+    /// After the peer's `Close` has been returned, further calls fail with
+    /// `WsError::Closed`. Nothing arriving within the read timeout (see
+    /// [`set_read_timeout`](Self::set_read_timeout)) is an `Io` error too.
+    pub fn recv(&mut self) -> Result<Message, WsError> {
+        if self.received_close {
+            return Err(WsError::Closed);
+        }
+        match self.read_message() {
+            Err(WsError::Protocol { code, reason }) => {
+                // 对端违反了协议，回一个 close 帧后就不再读了
+                self.received_close = true;
+                if !self.sent_close {
+                    let _ = self.send(Message::Close(Some(CloseFrame { code, reason: reason.to_string() })));
+                }
+                Err(WsError::Protocol { code, reason })
+            }
+            other => other,
+        }
+    }
+
+    fn read_message(&mut self) -> Result<Message, WsError> {
+        loop {
+            let frame = self.read_frame()?;
+            match frame.opcode {
+                OP_PING => {
+                    if !self.sent_close {
+                        self.write_frame(OP_PONG, &frame.payload)?;
+                    }
+                    return Ok(Message::Ping(frame.payload));
+                }
+                OP_PONG => return Ok(Message::Pong(frame.payload)),
+                OP_CLOSE => {
+                    let close = parse_close(&frame.payload)?;
+                    self.received_close = true;
+                    // 收到 close 要回一个，状态码原样带回去
+                    if !self.sent_close {
+                        self.send(Message::Close(close.clone()))?;
+                    }
+                    return Ok(Message::Close(close));
+                }
+                OP_TEXT | OP_BINARY => {
+                    if self.fragments.is_some() {
+                        return Err(protocol(close_code::PROTOCOL_ERROR, "expected a continuation frame"));
+                    }
+                    if frame.fin {
+                        return message(frame.opcode, frame.payload);
+                    }
+                    self.fragments = Some((frame.opcode, frame.payload));
+                }
+                OP_CONTINUATION => {
+                    let Some((opcode, mut data)) = self.fragments.take() else {
+                        return Err(protocol(close_code::PROTOCOL_ERROR, "continuation without a message"));
+                    };
+                    if data.len() + frame.payload.len() > self.max_message {
+                        return Err(protocol(close_code::MESSAGE_TOO_BIG, "message too large"));
+                    }
+                    data.extend_from_slice(&frame.payload);
+                    if frame.fin {
+                        return message(opcode, data);
+                    }
+                    self.fragments = Some((opcode, data));
+                }
+                _ => return Err(protocol(close_code::PROTOCOL_ERROR, "unknown opcode")),
+            }
+        }
+    }
+
+    fn read_frame(&mut self) -> Result<Frame, WsError> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            if let Some(frame) = parse_frame(&mut self.buf, self.max_message)? {
+                return Ok(frame);
+            }
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                Ok(n) => self.buf.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Sends one unfragmented message. Sending `Close` starts the closing
+    /// handshake: keep calling `recv` until it returns the peer's `Close`.
+    pub fn send(&mut self, msg: Message) -> Result<(), WsError> {
+        if self.sent_close {
+            return Err(WsError::Closed);
+        }
+        let (opcode, payload) = match msg {
+            Message::Text(s) => (OP_TEXT, s.into_bytes()),
+            Message::Binary(b) => (OP_BINARY, b),
+            Message::Ping(b) => (OP_PING, b),
+            Message::Pong(b) => (OP_PONG, b),
+            Message::Close(frame) => {
+                if frame.as_ref().is_some_and(|f| !close_code::is_valid(f.code)) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid close code").into());
+                }
+                self.sent_close = true;
+                (OP_CLOSE, close_payload(&frame))
+            }
+        };
+        if opcode >= 0x8 && payload.len() > 125 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "control frame payload over 125 bytes").into());
+        }
+        self.write_frame(opcode, &payload)
+    }
+
+    /// Sends a close frame with `code` and `reason`.
+    pub fn close(&mut self, code: u16, reason: &str) -> Result<(), WsError> {
+        self.send(Message::Close(Some(CloseFrame { code, reason: reason.to_string() })))
+    }
+
+    fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<(), WsError> {
+        self.stream.write_all(&encode_frame(opcode, payload))?;
+        // TLS 的数据可能还在 rustls 的缓冲区里
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    // handler 返回后：还没发 close 就补一个，再等一会对端的 close，然后由 conn 关闭 TCP 连接
+    fn finish(&mut self) -> Result<(), WsError> {
+        if !self.sent_close {
+            self.close(close_code::NORMAL, "")?;
+        }
+        self.set_read_timeout(Some(Duration::from_secs(1)))?;
+        while !self.received_close {
+            self.recv()?;
+        }
+        Ok(())
+    }
+}
+
+fn message(opcode: u8, data: Vec<u8>) -> Result<Message, WsError> {
+    if opcode == OP_TEXT {
+        String::from_utf8(data)
+            .map(Message::Text)
+            .map_err(|_| protocol(close_code::INVALID_PAYLOAD, "text message is not utf-8"))
+    } else {
+        Ok(Message::Binary(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+
+    // 客户端的帧：带掩码，可以分片
+    fn client_frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x12, 0x34, 0x56, 0x78];
+        let mut out = encode_frame(opcode, payload);
+        let header = out.len() - payload.len();
+        if !fin {
+            out[0] &= 0x7F;
+        }
+        out[1] |= 0x80;
+        let mut masked = payload.to_vec();
+        apply_mask(&mut masked, mask);
+        out.truncate(header);
+        out.extend_from_slice(&mask);
+        out.extend_from_slice(&masked);
+        out
+    }
+
+    fn pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        (listener.accept().unwrap().0, client)
+    }
+
+    #[test]
+    fn accept_key_from_the_rfc() {
+        assert_eq!(accept_for("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn frame_parsing() {
+        let mut buf = client_frame(true, OP_TEXT, &[b'x'; 300]);
+        let partial = buf.split_off(3);
+        let mut head = buf.clone();
+        assert!(parse_frame(&mut head, 1024).unwrap().is_none());
+        buf.extend_from_slice(&partial);
+        let frame = parse_frame(&mut buf, 1024).unwrap().unwrap();
+        assert_eq!((frame.fin, frame.opcode, frame.payload.len()), (true, OP_TEXT, 300));
+        assert!(buf.is_empty());
+
+        let mut unmasked = encode_frame(OP_TEXT, b"hi");
+        assert!(matches!(parse_frame(&mut unmasked, 1024), Err(WsError::Protocol { code: 1002, .. })));
+        let mut long_ping = client_frame(true, OP_PING, &[0; 126]);
+        assert!(matches!(parse_frame(&mut long_ping, 1024), Err(WsError::Protocol { code: 1002, .. })));
+        let mut big = client_frame(true, OP_BINARY, &[0; 2000]);
+        assert!(matches!(parse_frame(&mut big, 1024), Err(WsError::Protocol { code: 1009, .. })));
+    }
+
+    #[test]
+    fn close_codes() {
+        assert_eq!(parse_close(&[]).unwrap(), None);
+        assert_eq!(
+            parse_close(&[0x03, 0xE8, b'o', b'k']).unwrap(),
+            Some(CloseFrame { code: 1000, reason: "ok".to_string() })
+        );
+        for code in [999u16, 1004, 1005, 1006, 1015, 2000, 5000] {
+            assert!(parse_close(&code.to_be_bytes()).is_err(), "{code}");
+        }
+        assert!(matches!(parse_close(&[0x03]), Err(WsError::Protocol { code: 1002, .. })));
+        assert!(matches!(parse_close(&[0x03, 0xE8, 0xFF]), Err(WsError::Protocol { code: 1007, .. })));
+    }
+
+    #[test]
+    fn fragments_pings_and_close() {
+        let (mut server, mut client) = pair();
+        let mut input = client_frame(false, OP_TEXT, b"hel");
+        // 分片中间可以插入控制帧
+        input.extend(client_frame(true, OP_PING, b"p"));
+        input.extend(client_frame(true, OP_CONTINUATION, b"lo"));
+        input.extend(client_frame(true, OP_CLOSE, &[0x0F, 0xA0]));
+        let mut ws = WebSocket::new(&mut server, input);
+
+        assert_eq!(ws.recv().unwrap(), Message::Ping(b"p".to_vec()));
+        assert_eq!(ws.recv().unwrap(), Message::Text("hello".to_string()));
+        assert_eq!(ws.recv().unwrap(), Message::Close(Some(CloseFrame { code: 4000, reason: String::new() })));
+        assert!(matches!(ws.recv(), Err(WsError::Closed)));
+        assert!(matches!(ws.send(Message::Text("late".into())), Err(WsError::Closed)));
+
+        // pong 和 close 的回显，状态码原样带回
+        let mut out = [0u8; 7];
+        client.read_exact(&mut out).unwrap();
+        assert_eq!(out, [0x8A, 1, b'p', 0x88, 2, 0x0F, 0xA0]);
+    }
+
+    #[test]
+    fn protocol_error_sends_close() {
+        let (mut server, mut client) = pair();
+        let mut input = client_frame(true, OP_CONTINUATION, b"x");
+        input.extend(client_frame(true, OP_TEXT, &[0xFF]));
+        let mut ws = WebSocket::new(&mut server, input);
+        assert!(matches!(ws.recv(), Err(WsError::Protocol { code: 1002, .. })));
+        assert!(matches!(ws.recv(), Err(WsError::Closed)));
+
+        let mut out = [0u8; 4];
+        client.read_exact(&mut out).unwrap();
+        assert_eq!(out[0], 0x88);
+        assert_eq!(out[2..], [0x03, 0xEA]);
+    }
+}
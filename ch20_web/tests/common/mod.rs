@@ -0,0 +1,73 @@
+// 与仓库根目录的 tests/common 一样用 mod.rs，避免被 cargo 当成单独的测试文件
+#![allow(dead_code)]
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use ch20_web::reactor::ReactorServer;
+use ch20_web::server::Server;
+
+pub struct TestServer {
+    pub addr: SocketAddr,
+    // 两种 server 的 ShutdownHandle 类型不同
+    shutdown: Box<dyn FnOnce() + Send>,
+    thread: JoinHandle<()>,
+}
+
+impl TestServer {
+    /// Shuts the server down and waits for `run` to return.
+    pub fn stop(self) {
+        (self.shutdown)();
+        self.thread.join().unwrap();
+    }
+}
+
+/// Runs `server` (bound to an ephemeral port) on a background thread.
+pub fn start(server: Server) -> TestServer {
+    let addr = server.local_addr();
+    let handle = server.shutdown_handle();
+    let thread = thread::spawn(move || server.run());
+    TestServer { addr, shutdown: Box::new(move || handle.shutdown()), thread }
+}
+
+pub fn start_reactor(server: ReactorServer) -> TestServer {
+    let addr = server.local_addr();
+    let handle = server.shutdown_handle();
+    let thread = thread::spawn(move || server.run().unwrap());
+    TestServer { addr, shutdown: Box::new(move || handle.shutdown()), thread }
+}
+
+/// Sends a raw request and reads until the server closes the connection.
+pub fn send(addr: SocketAddr, raw: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(raw.as_bytes()).unwrap();
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    out
+}
+
+pub fn get(addr: SocketAddr, path: &str) -> String {
+    send(addr, &format!("GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"))
+}
+
+// 每 100ms 发一个 header 字节，永远发不完；返回 server 最后的回复
+pub fn slowloris(addr: SocketAddr) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\nX-Slow: ").unwrap();
+    let mut buf = [0u8; 1024];
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(10) {
+        match stream.read(&mut buf) {
+            Ok(n) => return String::from_utf8_lossy(&buf[..n]).into_owned(),
+            Err(_) => {
+                if stream.write_all(b"a").is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    String::new()
+}
@@ -0,0 +1,207 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ch20_web::conn::ConnConfig;
+use ch20_web::reactor::ReactorServer;
+use ch20_web::request::Request;
+use ch20_web::response::Response;
+use ch20_web::router::Router;
+
+fn router() -> Router {
+    let mut router = Router::new();
+    router.get("/", |_: &Request| Response::ok().text("hello"));
+    router.get("/slow", |_: &Request| {
+        thread::sleep(Duration::from_millis(500));
+        Response::ok().text("slow done")
+    });
+    router.get("/panic", |_: &Request| -> Response { panic!("boom") });
+    router.post("/len", |req: &Request| Response::ok().text(req.body.len().to_string()));
+    router
+}
+
+#[test]
+fn serves_and_stops() {
+    let server = common::start_reactor(ReactorServer::bind("127.0.0.1:0", router()).unwrap());
+    let addr = server.addr;
+    assert!(common::get(addr, "/").ends_with("hello"));
+    assert!(common::get(addr, "/missing").starts_with("HTTP/1.1 404"));
+    assert!(common::get(addr, "/panic").starts_with("HTTP/1.1 500"));
+
+    server.stop();
+    assert!(TcpStream::connect(addr).is_err());
+}
+
+#[test]
+fn pipelined_requests_are_answered_in_order() {
+    let server = common::start_reactor(ReactorServer::bind("127.0.0.1:0", router()).unwrap());
+    let out = common::send(
+        server.addr,
+        "GET /slow HTTP/1.1\r\n\r\nHEAD / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\nConnection: close\r\n\r\n",
+    );
+    let responses: Vec<&str> = out.split("HTTP/1.1 200 OK\r\n").skip(1).collect();
+    assert_eq!(responses.len(), 3);
+    assert!(responses[0].ends_with("slow done"));
+    assert!(responses[1].ends_with("Content-Length: 5\r\n\r\n"));
+    assert!(responses[2].ends_with("hello"));
+    server.stop();
+}
+
+// threaded 模式下一个 worker 会被这个空闲连接占住，第二个客户端要等 idle_timeout
+#[test]
+fn idle_connection_does_not_take_a_worker() {
+    let server = common::start_reactor(ReactorServer::bind("127.0.0.1:0", router()).unwrap().workers(1));
+    let mut idle = TcpStream::connect(server.addr).unwrap();
+    idle.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let mut buf = [0u8; 1024];
+    let n = idle.read(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).ends_with("hello"));
+
+    let start = Instant::now();
+    assert!(common::get(server.addr, "/").ends_with("hello"));
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    // shutdown 时空闲连接被马上关掉
+    let start = Instant::now();
+    server.stop();
+    assert!(start.elapsed() < Duration::from_secs(2));
+    assert_eq!(idle.read(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn in_flight_request_finishes_before_shutdown_returns() {
+    let server = common::start_reactor(ReactorServer::bind("127.0.0.1:0", router()).unwrap());
+    let addr = server.addr;
+    let client = thread::spawn(move || common::get(addr, "/slow"));
+    thread::sleep(Duration::from_millis(100));
+
+    server.stop();
+    let resp = client.join().unwrap();
+    assert!(resp.starts_with("HTTP/1.1 200 OK"));
+    assert!(resp.ends_with("slow done"));
+}
+
+// 三个请求加起来超过了 max_head + max_body，事件循环分几次读，不会一次全读进内存
+#[test]
+fn pipelined_requests_larger_than_the_limits() {
+    let mut config = ConnConfig::default();
+    config.limits.max_head = 1024;
+    config.limits.max_body = 16 * 1024;
+    let server = common::start_reactor(ReactorServer::bind("127.0.0.1:0", router()).unwrap().conn_config(config));
+    let body = "a".repeat(12 * 1024);
+    let post = format!("POST /len HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}", body.len());
+    let last = format!("POST /len HTTP/1.1\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}", body.len());
+    let out = common::send(server.addr, &format!("{post}{post}{last}"));
+    assert_eq!(out.matches("\r\n\r\n12288").count(), 3);
+    server.stop();
+}
+
+// 事件驱动模式下慢客户端不占 worker，但也不能让它一直占着连接
+#[test]
+fn slow_clients_get_408() {
+    let config = ConnConfig { request_timeout: Duration::from_millis(500), ..ConnConfig::default() };
+    let server = common::start_reactor(ReactorServer::bind("127.0.0.1:0", router()).unwrap().conn_config(config));
+    let addr = server.addr;
+
+    let start = Instant::now();
+    let slow: Vec<_> = (0..6).map(|_| thread::spawn(move || common::slowloris(addr))).collect();
+    thread::sleep(Duration::from_millis(100));
+    assert!(common::get(addr, "/").ends_with("hello"));
+    for client in slow {
+        let resp = client.join().unwrap();
+        assert!(resp.starts_with("HTTP/1.1 408 Request Timeout\r\n"), "{resp}");
+    }
+    assert!(start.elapsed() < Duration::from_secs(3));
+
+    server.stop();
+}
+
+#[test]
+fn per_ip_connection_cap_answers_429() {
+    let server = common::start_reactor(ReactorServer::bind("127.0.0.1:0", router()).unwrap().max_conns_per_ip(2));
+    let addr = server.addr;
+
+    let first = TcpStream::connect(addr).unwrap();
+    let _second = TcpStream::connect(addr).unwrap();
+    let resp = common::get(addr, "/");
+    assert!(resp.starts_with("HTTP/1.1 429 Too Many Requests\r\n"), "{resp}");
+
+    drop(first);
+    thread::sleep(Duration::from_millis(300));
+    assert!(common::get(addr, "/").ends_with("hello"));
+
+    server.stop();
+}
+
+// 客户端发了请求却不读响应，写不出去的连接过了 write_timeout 就关掉
+#[test]
+fn stalled_writes_are_closed() {
+    let config = ConnConfig { write_timeout: Duration::from_millis(300), ..ConnConfig::default() };
+    let mut router = router();
+    router.get("/big", |_: &Request| Response::ok().text("a".repeat(8 << 20)));
+    let server = common::start_reactor(ReactorServer::bind("127.0.0.1:0", router).unwrap().conn_config(config));
+
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    stream.write_all(b"GET /big HTTP/1.1\r\nAccept-Encoding: identity\r\n\r\n").unwrap();
+    thread::sleep(Duration::from_secs(1));
+    // 连接已经被关掉了，读完内核缓冲区里剩下的就是 EOF(或者 RST)
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut total = 0;
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => total += n,
+        }
+    }
+    assert!(total < 8 << 20);
+    server.stop();
+}
+
+#[test]
+fn full_queue_answers_503() {
+    let server = ReactorServer::bind("127.0.0.1:0", router()).unwrap().workers(1).queue_capacity(1);
+    let server = common::start_reactor(server);
+    let addr = server.addr;
+
+    // 一个 /slow 占着唯一的 worker，另一个排在队列里，第三个请求被拒绝
+    let slow: Vec<_> = (0..2)
+        .map(|_| {
+            let client = thread::spawn(move || common::get(addr, "/slow"));
+            thread::sleep(Duration::from_millis(100));
+            client
+        })
+        .collect();
+    let resp = common::get(addr, "/");
+    assert!(resp.starts_with("HTTP/1.1 503 Service Unavailable\r\n"), "{resp}");
+    for client in slow {
+        assert!(client.join().unwrap().ends_with("slow done"));
+    }
+
+    server.stop();
+}
+
+// 客户端读得慢，但一直在读：write_timeout 比 idle_timeout 长时，不能按 idle_timeout 关掉
+#[test]
+fn slow_reader_gets_write_timeout_not_idle_timeout() {
+    let config = ConnConfig {
+        idle_timeout: Duration::from_millis(300),
+        write_timeout: Duration::from_secs(5),
+        ..ConnConfig::default()
+    };
+    let mut router = router();
+    router.get("/big", |_: &Request| Response::ok().text("a".repeat(8 << 20)));
+    let server = common::start_reactor(ReactorServer::bind("127.0.0.1:0", router).unwrap().conn_config(config));
+
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    stream.write_all(b"GET /big HTTP/1.1\r\nAccept-Encoding: identity\r\nConnection: close\r\n\r\n").unwrap();
+    // 先一直不读，超过 idle_timeout，但没超过 write_timeout
+    thread::sleep(Duration::from_secs(1));
+    let mut out = Vec::new();
+    stream.read_to_end(&mut out).unwrap();
+    assert!(out.len() > 8 << 20);
+    assert!(out.ends_with(b"aaaa"));
+    server.stop();
+}
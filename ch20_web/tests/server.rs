@@ -0,0 +1,200 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ch20_web::conn::ConnConfig;
+use ch20_web::request::Request;
+use ch20_web::response::Response;
+use ch20_web::router::Router;
+use ch20_web::server::Server;
+use ch20_web::threadpool::OverflowPolicy;
+
+fn router() -> Router {
+    let mut router = Router::new();
+    router.get("/", |_: &Request| Response::ok().text("hello"));
+    router.get("/slow", |_: &Request| {
+        thread::sleep(Duration::from_millis(500));
+        Response::ok().text("slow done")
+    });
+    router
+}
+
+#[test]
+fn serves_on_ephemeral_port_and_stops() {
+    let server = common::start(Server::bind("127.0.0.1:0", router()).unwrap());
+    let addr = server.addr;
+    assert!(common::get(addr, "/").ends_with("hello"));
+
+    server.stop();
+    // listener 已经关闭
+    assert!(TcpStream::connect(addr).is_err());
+}
+
+#[test]
+fn in_flight_request_finishes_before_shutdown_returns() {
+    let server = common::start(Server::bind("127.0.0.1:0", router()).unwrap());
+    let addr = server.addr;
+    let client = thread::spawn(move || common::get(addr, "/slow"));
+    // 等请求进入 handler
+    thread::sleep(Duration::from_millis(100));
+
+    server.stop();
+    let resp = client.join().unwrap();
+    assert!(resp.starts_with("HTTP/1.1 200 OK"));
+    assert!(resp.ends_with("slow done"));
+}
+
+#[test]
+fn idle_keep_alive_connection_does_not_delay_shutdown() {
+    let server = common::start(Server::bind("127.0.0.1:0", router()).unwrap());
+    let mut client = TcpStream::connect(server.addr).unwrap();
+    client.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let mut buf = [0u8; 1024];
+    let n = client.read(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).ends_with("hello"));
+
+    // 连接现在空闲，默认 idle_timeout 是 5 秒
+    let start = Instant::now();
+    server.stop();
+    assert!(start.elapsed() < Duration::from_secs(2));
+    assert_eq!(client.read(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn shutdown_timeout_bounds_the_wait() {
+    let server = Server::bind("127.0.0.1:0", router())
+        .unwrap()
+        .shutdown_timeout(Duration::from_millis(100));
+    let server = common::start(server);
+    let addr = server.addr;
+    let client = thread::spawn(move || {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /slow HTTP/1.1\r\n\r\n").unwrap();
+        let mut out = Vec::new();
+        // 超时后 server 关掉了 socket，拿不到响应
+        let _ = stream.read_to_end(&mut out);
+        out
+    });
+    thread::sleep(Duration::from_millis(100));
+
+    server.stop();
+    assert!(client.join().unwrap().is_empty());
+}
+
+#[test]
+fn full_queue_answers_503() {
+    let server = Server::bind("127.0.0.1:0", router())
+        .unwrap()
+        .workers(1)
+        .queue(1, OverflowPolicy::Reject);
+    let server = common::start(server);
+    let addr = server.addr;
+
+    // 唯一的 worker 被这个 keep-alive 连接占着
+    let mut busy = TcpStream::connect(addr).unwrap();
+    busy.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let mut buf = [0u8; 1024];
+    let n = busy.read(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).ends_with("hello"));
+
+    // 第二个连接排进队列，第三个被拒绝
+    let _queued = TcpStream::connect(addr).unwrap();
+    let resp = common::get(addr, "/");
+    assert!(resp.starts_with("HTTP/1.1 503 Service Unavailable\r\n"), "{resp}");
+
+    server.stop();
+}
+
+#[test]
+fn metrics_endpoint_reports_pool_stats() {
+    let mut router = router();
+    // catch-all 路由也挡不住 /metrics
+    router.get("/{*path}", |_: &Request| Response::ok().text("catch-all"));
+    let server = common::start(Server::bind("127.0.0.1:0", router).unwrap().metrics("/metrics"));
+    let addr = server.addr;
+
+    assert!(common::get(addr, "/").ends_with("hello"));
+    let resp = common::get(addr, "/metrics");
+    assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{resp}");
+    assert!(resp.contains("Content-Type: text/plain; version=0.0.4"));
+    assert!(resp.contains("# TYPE ch20_web_pool_jobs_completed_total counter\n"));
+    assert!(resp.contains("ch20_web_pool_workers{state=\"busy\"} "), "{resp}");
+    assert!(common::get(addr, "/other").ends_with("catch-all"));
+
+    server.stop();
+}
+
+#[test]
+fn listens_on_every_configured_address() {
+    let server = Server::bind_all(["127.0.0.1:0", "127.0.0.1:0"], router()).unwrap();
+    let addrs = server.local_addrs().to_vec();
+    assert_eq!(addrs.len(), 2);
+    let server = common::start(server);
+    for addr in &addrs {
+        assert!(common::get(*addr, "/").ends_with("hello"));
+    }
+
+    server.stop();
+    for addr in &addrs {
+        assert!(TcpStream::connect(addr).is_err());
+    }
+}
+
+#[test]
+fn slow_clients_cannot_starve_the_workers() {
+    let config = ConnConfig { request_timeout: Duration::from_millis(500), ..ConnConfig::default() };
+    let server = Server::bind("127.0.0.1:0", router()).unwrap().workers(4).conn_config(config);
+    let server = common::start(server);
+    let addr = server.addr;
+
+    // 比 worker 还多的慢客户端，每个都在 idle_timeout 之内不停地发字节
+    let slow: Vec<_> = (0..6).map(|_| thread::spawn(move || common::slowloris(addr))).collect();
+    thread::sleep(Duration::from_millis(100));
+
+    let start = Instant::now();
+    assert!(common::get(addr, "/").ends_with("hello"));
+    // 要等慢客户端被踢掉腾出 worker，但不会被它们一直占着
+    assert!(start.elapsed() < Duration::from_secs(3));
+    for client in slow {
+        let resp = client.join().unwrap();
+        assert!(resp.starts_with("HTTP/1.1 408 Request Timeout\r\n"), "{resp}");
+    }
+
+    server.stop();
+}
+
+#[test]
+fn limits_answer_413_and_431() {
+    let server = common::start(Server::bind("127.0.0.1:0", router()).unwrap());
+    let addr = server.addr;
+
+    let headers: String = (0..200).map(|i| format!("X-H{i}: {i}\r\n")).collect();
+    let resp = common::send(addr, &format!("GET / HTTP/1.1\r\n{headers}\r\n"));
+    assert!(resp.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"), "{resp}");
+
+    let resp = common::send(addr, "POST / HTTP/1.1\r\nContent-Length: 2000000\r\n\r\n");
+    assert!(resp.starts_with("HTTP/1.1 413 Content Too Large\r\n"), "{resp}");
+
+    server.stop();
+}
+
+#[test]
+fn per_ip_connection_cap_answers_429() {
+    let server = common::start(Server::bind("127.0.0.1:0", router()).unwrap().max_conns_per_ip(2));
+    let addr = server.addr;
+
+    let first = TcpStream::connect(addr).unwrap();
+    let _second = TcpStream::connect(addr).unwrap();
+    let resp = common::get(addr, "/");
+    assert!(resp.starts_with("HTTP/1.1 429 Too Many Requests\r\n"), "{resp}");
+
+    // 关掉一个之后名额就空出来了
+    drop(first);
+    thread::sleep(Duration::from_millis(100));
+    assert!(common::get(addr, "/").ends_with("hello"));
+
+    server.stop();
+}
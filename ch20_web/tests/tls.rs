@@ -0,0 +1,60 @@
+#![cfg(feature = "tls")]
+
+mod common;
+
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+
+use ch20_web::request::Request;
+use ch20_web::response::Response;
+use ch20_web::router::Router;
+use ch20_web::server::Server;
+use rustls::pki_types::{CertificateDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+
+// 测试时现场生成自签名证书，写到临时目录里走一遍 load_config
+fn self_signed() -> (Arc<rustls::ServerConfig>, CertificateDer<'static>) {
+    let rcgen::CertifiedKey { cert, key_pair } = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    let dir = std::env::temp_dir().join(format!("ch20_web_tls_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("cert.pem"), cert.pem()).unwrap();
+    fs::write(dir.join("key.pem"), key_pair.serialize_pem()).unwrap();
+    let config = ch20_web::tls::load_config(dir.join("cert.pem"), dir.join("key.pem")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    (config, cert.der().clone())
+}
+
+fn router() -> Router {
+    let mut router = Router::new();
+    router.get("/", |_: &Request| Response::ok().text("hello over tls"));
+    router
+}
+
+#[test]
+fn serves_https() {
+    let (config, cert) = self_signed();
+    let server = common::start(Server::bind("127.0.0.1:0", router()).unwrap().tls(config));
+
+    let mut roots = RootCertStore::empty();
+    roots.add(cert).unwrap();
+    let client_config = ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+    let conn = ClientConnection::new(Arc::new(client_config), ServerName::try_from("localhost").unwrap()).unwrap();
+    let mut tls = StreamOwned::new(conn, TcpStream::connect(server.addr).unwrap());
+    tls.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    let mut out = String::new();
+    // server 关闭前发了 close_notify，这里能正常读到 EOF
+    tls.read_to_string(&mut out).unwrap();
+    assert!(out.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(out.ends_with("hello over tls"));
+
+    // 明文请求握手失败，连接直接被关掉
+    let mut plain = TcpStream::connect(server.addr).unwrap();
+    plain.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let mut buf = Vec::new();
+    let _ = plain.read_to_end(&mut buf);
+    assert!(!buf.starts_with(b"HTTP/1.1 200"));
+
+    server.stop();
+}
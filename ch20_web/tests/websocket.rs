@@ -0,0 +1,115 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use ch20_web::conn::ConnConfig;
+use ch20_web::request::Request;
+use ch20_web::router::Router;
+use ch20_web::server::Server;
+use ch20_web::websocket::{self, Message};
+
+fn router() -> Router {
+    let mut router = Router::new();
+    router.get("/echo", |req: &Request| {
+        websocket::upgrade(req, |ws| {
+            while let Ok(msg) = ws.recv() {
+                let echo = matches!(msg, Message::Text(_) | Message::Binary(_));
+                if echo && ws.send(msg).is_err() {
+                    break;
+                }
+            }
+        })
+    });
+    router
+}
+
+fn handshake(stream: &mut TcpStream, version: &str) -> String {
+    write!(
+        stream,
+        "GET /echo HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: keep-alive, Upgrade\r\n\
+         Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: {version}\r\n\r\n"
+    )
+    .unwrap();
+    // 一个字节一个字节读到空行，后面的数据已经是帧了
+    let mut head = Vec::new();
+    let mut b = [0u8];
+    while !head.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut b).unwrap();
+        head.push(b[0]);
+    }
+    String::from_utf8(head).unwrap()
+}
+
+// 客户端发出的帧必须带掩码
+fn frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mask = [1, 2, 3, 4];
+    assert!(payload.len() < 126);
+    let mut out = vec![if fin { 0x80 } else { 0 } | opcode, 0x80 | payload.len() as u8];
+    out.extend_from_slice(&mask);
+    out.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    out
+}
+
+fn read_frame(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+    let mut head = [0u8; 2];
+    stream.read_exact(&mut head).unwrap();
+    assert_eq!(head[1] & 0x80, 0, "server frames are not masked");
+    let mut payload = vec![0u8; (head[1] & 0x7F) as usize];
+    stream.read_exact(&mut payload).unwrap();
+    (head[0], payload)
+}
+
+#[test]
+fn echo_and_close() {
+    let server = common::start(Server::bind("127.0.0.1:0", router()).unwrap());
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    let head = handshake(&mut stream, "13");
+    assert!(head.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+    assert!(head.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+    assert!(head.contains("Connection: Upgrade\r\n"));
+
+    let mut out = frame(false, 0x1, b"hello, ");
+    out.extend(frame(true, 0x9, b"ping"));
+    out.extend(frame(true, 0x0, b"world"));
+    stream.write_all(&out).unwrap();
+    assert_eq!(read_frame(&mut stream), (0x8A, b"ping".to_vec()));
+    assert_eq!(read_frame(&mut stream), (0x81, b"hello, world".to_vec()));
+
+    // 1000 + reason，server 原样回一个 close，然后关闭连接
+    stream.write_all(&frame(true, 0x8, b"\x03\xe8bye")).unwrap();
+    assert_eq!(read_frame(&mut stream), (0x88, b"\x03\xe8bye".to_vec()));
+    assert_eq!(stream.read(&mut [0u8; 16]).unwrap(), 0);
+
+    server.stop();
+}
+
+#[test]
+fn wrong_version_is_426() {
+    let server = common::start(Server::bind("127.0.0.1:0", router()).unwrap());
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    let head = handshake(&mut stream, "8");
+    assert!(head.starts_with("HTTP/1.1 426 Upgrade Required\r\n"));
+    assert!(head.contains("Sec-WebSocket-Version: 13\r\n"));
+    assert!(common::get(server.addr, "/echo").starts_with("HTTP/1.1 400"));
+    server.stop();
+}
+
+// 什么都不发的 WebSocket 客户端过了 idle_timeout 就被关掉，worker 空出来给别人
+#[test]
+fn idle_websocket_releases_the_worker() {
+    let config = ConnConfig { idle_timeout: Duration::from_millis(300), ..ConnConfig::default() };
+    let server = Server::bind("127.0.0.1:0", router()).unwrap().workers(1).conn_config(config);
+    let server = common::start(server);
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    assert!(handshake(&mut stream, "13").starts_with("HTTP/1.1 101"));
+
+    let start = Instant::now();
+    assert_eq!(read_frame(&mut stream), (0x88, b"\x03\xe8".to_vec()));
+    assert!(start.elapsed() < Duration::from_secs(2));
+    stream.write_all(&frame(true, 0x8, b"\x03\xe8")).unwrap();
+    assert!(common::get(server.addr, "/echo").starts_with("HTTP/1.1 400"));
+
+    server.stop();
+}
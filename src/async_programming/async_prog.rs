@@ -20,4 +20,5 @@ pub fn t_async_main() {
     ch7_entrypoint::t_coroutine_main();
     //ch8_entrypoint_native_runtime::t_run_coro_with_mioPoll();
     //entrypoint::t_run_reactor_executor();
+    //entrypoint::t_run_timeout();
 }
\ No newline at end of file
@@ -1,11 +1,22 @@
 use super::http;
 use super::runtime::{self, Waker};
+use std::time::Duration;
 
 use super::future::{Future, PollState};
 
 pub fn t_run_reactor_executor() {
-    let mut executor = runtime::init();
-    executor.block_on(async_main());
+    let mut rt = runtime::init();
+    rt.block_on(async_main());
+}
+
+// delay server 2 秒后才回复，1 秒的 timeout 先到期
+pub fn t_run_timeout() {
+    let mut rt = runtime::init();
+    let fut = runtime::timeout(http::Http::get("/2000/too-slow"), Duration::from_secs(1));
+    match rt.block_on(fut) {
+        Ok(txt) => println!("{txt}"),
+        Err(e) => println!("request failed: {e}"),
+    }
 }
 
 
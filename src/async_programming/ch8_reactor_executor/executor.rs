@@ -2,6 +2,7 @@ use super::future::{Future, PollState};
 use std::{
     cell::{Cell, RefCell},
     collections::HashMap,
+    rc::Rc,
     sync::{Arc, Mutex},
     thread::{self, Thread},
 };
@@ -30,7 +31,8 @@ impl Waker {
     }
 }
 
-type Task = Box<dyn Future<Output = String>>;
+// 不同 task 的 Output 类型不同，放进同一个 HashMap 之前统一包装成 Output = ()，结果通过 JoinHandle 取
+type Task = Box<dyn Future<Output = ()>>;
 
 thread_local! {
     static CURRENT_EXEC: ExecutorCore = ExecutorCore::default();
@@ -57,15 +59,80 @@ struct ExecutorCore {
     next_id: Cell<usize>,
 }
 
-pub fn spawn<F>(future: F)
-    where F: Future<Output = String> + 'static {
+// executor 只在一个线程上运行，task 和它的 JoinHandle 用 Rc<RefCell> 共享就够了
+struct JoinState<T> {
+    output: Option<T>,
+    // 正在 await 这个 JoinHandle 的 task，结果出来时唤醒它
+    waker: Option<Waker>,
+}
+
+/// Resolves to the output of a task started with `spawn`. Dropping it
+/// detaches the task, which still runs to completion.
+pub struct JoinHandle<T> {
+    state: Rc<RefCell<JoinState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        let mut state = self.state.borrow_mut();
+        match state.output.take() {
+            Some(output) => PollState::Ready(output),
+            None => {
+                state.waker = Some(waker.clone());
+                PollState::NotReady
+            }
+        }
+    }
+}
+
+// spawn 出去的 future 外面包一层，完成时把结果交给 JoinHandle
+struct Spawned<F: Future> {
+    future: F,
+    state: Rc<RefCell<JoinState<F::Output>>>,
+}
+
+impl<F: Future> Future for Spawned<F> {
+    type Output = ();
+
+    fn poll(&mut self, waker: &Waker) -> PollState<()> {
+        match self.future.poll(waker) {
+            PollState::Ready(output) => {
+                let mut state = self.state.borrow_mut();
+                state.output = Some(output);
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+                PollState::Ready(())
+            }
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}
+
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+    where F: Future + 'static, F::Output: 'static {
+    let state = Rc::new(RefCell::new(JoinState { output: None, waker: None }));
+    let task = Spawned { future, state: state.clone() };
     CURRENT_EXEC.with(|e| {
         let id = e.next_id.get();
-        e.tasks.borrow_mut().insert(id, Box::new(future));
+        e.tasks.borrow_mut().insert(id, Box::new(task));
         // 刚刚spawn，不可能ready，仍把task id 放入叫一个ready queue的容器里，显得怪
         e.ready_queue.lock().map(|mut q| q.push(id)).unwrap();
         e.next_id.set(id + 1);
     });
+    JoinHandle { state }
+}
+
+// Runtime 结束时丢掉还没完成的 task(比如 block_on 里 panic 了)，不然会留给这个线程上的下一个 Runtime。
+// 在锁外面 drop，task 的 drop 里可能还要用到 executor
+pub(super) fn clear() {
+    let tasks = CURRENT_EXEC.with(|e| {
+        e.ready_queue.lock().map(|mut q| q.clear()).unwrap();
+        std::mem::take(&mut *e.tasks.borrow_mut())
+    });
+    drop(tasks);
 }
 
 pub struct Executor;
@@ -105,9 +172,11 @@ impl Executor {
         CURRENT_EXEC.with(|q| q.tasks.borrow().len())
     }
 
-    pub fn block_on<F>(&mut self, future: F)
-        where F: Future<Output = String> + 'static {
-        spawn(future);
+    /// Runs `future` and everything spawned meanwhile until all of them are
+    /// done, then returns the output of `future`.
+    pub fn block_on<F>(&mut self, future: F) -> F::Output
+        where F: Future + 'static, F::Output: 'static {
+        let handle = spawn(future);
         loop {
             while let Some(id) = self.pop_ready() {
                 let mut fut = match self.get_future(id) {
@@ -144,5 +213,78 @@ impl Executor {
                 break;
             }
         }
+        // 所有 task 都结束了，结果一定已经在 handle 里
+        match handle.state.borrow_mut().output.take() {
+            Some(output) => output,
+            None => unreachable!("block_on future did not complete"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 第一次 poll 时唤醒自己并返回 NotReady，第二次返回 value
+    struct YieldOnce<T> {
+        value: Option<T>,
+        yielded: bool,
+    }
+
+    fn yield_once<T>(value: T) -> YieldOnce<T> {
+        YieldOnce { value: Some(value), yielded: false }
+    }
+
+    impl<T> Future for YieldOnce<T> {
+        type Output = T;
+
+        fn poll(&mut self, waker: &Waker) -> PollState<T> {
+            if !self.yielded {
+                self.yielded = true;
+                waker.wake();
+                return PollState::NotReady;
+            }
+            PollState::Ready(self.value.take().expect("polled after completion"))
+        }
+    }
+
+    // 手写的状态机: spawn 两个输出类型不同的 task，再依次 await 它们的 JoinHandle
+    enum Sum {
+        Start,
+        Waiting(JoinHandle<u32>, JoinHandle<&'static str>),
+        WaitingSecond(u32, JoinHandle<&'static str>),
+    }
+
+    impl Future for Sum {
+        type Output = (u32, &'static str);
+
+        fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+            loop {
+                match std::mem::replace(self, Sum::Start) {
+                    Sum::Start => *self = Sum::Waiting(spawn(yield_once(40)), spawn(yield_once("two"))),
+                    Sum::Waiting(mut a, b) => match a.poll(waker) {
+                        PollState::Ready(n) => *self = Sum::WaitingSecond(n + 2, b),
+                        PollState::NotReady => {
+                            *self = Sum::Waiting(a, b);
+                            return PollState::NotReady;
+                        }
+                    },
+                    Sum::WaitingSecond(n, mut b) => match b.poll(waker) {
+                        PollState::Ready(s) => return PollState::Ready((n, s)),
+                        PollState::NotReady => {
+                            *self = Sum::WaitingSecond(n, b);
+                            return PollState::NotReady;
+                        }
+                    },
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn block_on_returns_output_of_any_type() {
+        let mut executor = Executor::new();
+        assert_eq!(executor.block_on(yield_once(7u8)), 7);
+        assert_eq!(executor.block_on(Sum::Start), (42, "two"));
     }
 }
\ No newline at end of file
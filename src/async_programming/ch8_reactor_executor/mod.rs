@@ -2,6 +2,8 @@ pub mod future;
 pub mod reactor;
 pub mod executor;
 pub mod runtime;
+pub mod timer;
+pub mod net;
 pub mod http;
 pub mod entrypoint;
 
@@ -0,0 +1,328 @@
+// 建立在 reactor 上的非阻塞 TCP。socket 创建时就以 READABLE | WRITABLE 注册到 reactor，
+// 之后 accept/connect/read/write 遇到 WouldBlock 就返回 NotReady，等 reactor 唤醒再试，
+// 不会像 HttpGetFuture 原来那样在 executor 线程上阻塞。
+//
+// 每个 socket 在 reactor 里只有一个 waker，所以同一时间只能有一个 task 在等它
+use super::future::{Future, PollState};
+use super::reactor::Reactor;
+use super::runtime::{reactor, Waker};
+use mio::Interest;
+use std::{
+    io::{self, Read as _, Write as _},
+    net::SocketAddr,
+    sync::Arc,
+};
+
+// socket 在哪个 reactor 上用哪个 id 注册的；socket drop 时用它注销
+struct Registration {
+    reactor: Arc<Reactor>,
+    id: usize,
+}
+
+impl Registration {
+    fn new<S: mio::event::Source>(source: &mut S) -> io::Result<Registration> {
+        let reactor = reactor();
+        let id = reactor.next_id();
+        // mio 是边沿触发的，两个方向一起注册，之后不用再 reregister
+        reactor.register(source, Interest::READABLE | Interest::WRITABLE, id)?;
+        Ok(Registration { reactor, id })
+    }
+
+    /* Must always store the most recent waker, 而且要在尝试操作之前设置:
+    如果先 read 得到 WouldBlock 再 set_waker，数据恰好在两者之间到达的话，
+    reactor 找不到 waker，这次事件就丢了
+    https://github.com/PacktPublishing/Asynchronous-Programming-in-Rust/issues/23
+    */
+    fn poll_io<T>(&self, waker: &Waker, mut op: impl FnMut() -> io::Result<T>) -> PollState<io::Result<T>> {
+        self.reactor.set_waker(waker, self.id);
+        loop {
+            match op() {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return PollState::NotReady,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => return PollState::Ready(result),
+            }
+        }
+    }
+}
+
+pub struct TcpListener {
+    inner: mio::net::TcpListener,
+    registration: Registration,
+}
+
+impl TcpListener {
+    /// Binds a listener and registers it with the current runtime's reactor.
+    pub fn bind(addr: SocketAddr) -> io::Result<TcpListener> {
+        let mut inner = mio::net::TcpListener::bind(addr)?;
+        let registration = Registration::new(&mut inner)?;
+        Ok(TcpListener { inner, registration })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub fn poll_accept(&mut self, waker: &Waker) -> PollState<io::Result<(TcpStream, SocketAddr)>> {
+        let inner = &self.inner;
+        match self.registration.poll_io(waker, || inner.accept()) {
+            PollState::Ready(Ok((stream, addr))) => PollState::Ready(TcpStream::new(stream).map(|s| (s, addr))),
+            PollState::Ready(Err(e)) => PollState::Ready(Err(e)),
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+
+    /// Waits for the next connection.
+    pub fn accept(&mut self) -> Accept<'_> {
+        Accept { listener: self }
+    }
+}
+
+impl Drop for TcpListener {
+    fn drop(&mut self) {
+        let _ = self.registration.reactor.deregister(&mut self.inner, self.registration.id);
+    }
+}
+
+// 下面这些 future 除了借用之外没有别的状态，每次 poll 时重新创建也没关系，
+// 手写的状态机里可以直接调用 poll_accept/poll_read/poll_write
+pub struct Accept<'a> {
+    listener: &'a mut TcpListener,
+}
+
+impl Future for Accept<'_> {
+    type Output = io::Result<(TcpStream, SocketAddr)>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        self.listener.poll_accept(waker)
+    }
+}
+
+pub struct TcpStream {
+    inner: mio::net::TcpStream,
+    registration: Registration,
+}
+
+impl TcpStream {
+    fn new(mut inner: mio::net::TcpStream) -> io::Result<TcpStream> {
+        let registration = Registration::new(&mut inner)?;
+        Ok(TcpStream { inner, registration })
+    }
+
+    /// Opens a connection without blocking; the future resolves once it is
+    /// established or has failed.
+    pub fn connect(addr: SocketAddr) -> Connect {
+        Connect { addr, stream: None }
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    pub fn poll_read(&mut self, waker: &Waker, buf: &mut [u8]) -> PollState<io::Result<usize>> {
+        let inner = &mut self.inner;
+        self.registration.poll_io(waker, || inner.read(buf))
+    }
+
+    pub fn poll_write(&mut self, waker: &Waker, buf: &[u8]) -> PollState<io::Result<usize>> {
+        let inner = &mut self.inner;
+        self.registration.poll_io(waker, || inner.write(buf))
+    }
+
+    /// Reads some bytes into `buf`; `Ok(0)` means the peer closed its end.
+    pub fn read<'a>(&'a mut self, buf: &'a mut [u8]) -> ReadFuture<'a> {
+        ReadFuture { stream: self, buf }
+    }
+
+    /// Writes some bytes of `buf`, waiting for WRITABLE when the send buffer is full.
+    pub fn write<'a>(&'a mut self, buf: &'a [u8]) -> WriteFuture<'a> {
+        WriteFuture { stream: self, buf }
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        let _ = self.registration.reactor.deregister(&mut self.inner, self.registration.id);
+    }
+}
+
+pub struct Connect {
+    addr: SocketAddr,
+    stream: Option<TcpStream>,
+}
+
+impl Future for Connect {
+    type Output = io::Result<TcpStream>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        if self.stream.is_none() {
+            // 非阻塞的 connect 立刻返回，连接在后台建立
+            let stream = mio::net::TcpStream::connect(self.addr).and_then(TcpStream::new);
+            match stream {
+                Ok(stream) => self.stream = Some(stream),
+                Err(e) => return PollState::Ready(Err(e)),
+            }
+        }
+        let stream = self.stream.as_mut().unwrap();
+        stream.registration.reactor.set_waker(waker, stream.registration.id);
+        // mio 的文档: 等到 WRITABLE 后先看 take_error，再用 peer_addr 判断是否真的连上了
+        match stream.inner.take_error() {
+            Ok(Some(e)) | Err(e) => return PollState::Ready(Err(e)),
+            Ok(None) => {}
+        }
+        match stream.inner.peer_addr() {
+            Ok(_) => PollState::Ready(Ok(self.stream.take().unwrap())),
+            Err(e) if e.kind() == io::ErrorKind::NotConnected => PollState::NotReady,
+            Err(e) => PollState::Ready(Err(e)),
+        }
+    }
+}
+
+pub struct ReadFuture<'a> {
+    stream: &'a mut TcpStream,
+    buf: &'a mut [u8],
+}
+
+impl Future for ReadFuture<'_> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        self.stream.poll_read(waker, self.buf)
+    }
+}
+
+pub struct WriteFuture<'a> {
+    stream: &'a mut TcpStream,
+    buf: &'a [u8],
+}
+
+impl Future for WriteFuture<'_> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        self.stream.poll_write(waker, self.buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::runtime::{timeout, Runtime};
+    use super::*;
+    use std::{thread, time::Duration};
+
+    // 连上 addr，发 msg，读到对端关闭为止
+    enum Client {
+        Connecting(Connect, &'static [u8]),
+        Writing(TcpStream, &'static [u8]),
+        Reading(TcpStream, Vec<u8>),
+        Done,
+    }
+
+    impl Future for Client {
+        type Output = Vec<u8>;
+
+        fn poll(&mut self, waker: &Waker) -> PollState<Vec<u8>> {
+            loop {
+                match std::mem::replace(self, Client::Done) {
+                    Client::Connecting(mut connect, msg) => match connect.poll(waker) {
+                        PollState::Ready(stream) => *self = Client::Writing(stream.unwrap(), msg),
+                        PollState::NotReady => {
+                            *self = Client::Connecting(connect, msg);
+                            return PollState::NotReady;
+                        }
+                    },
+                    Client::Writing(stream, []) => *self = Client::Reading(stream, vec![]),
+                    Client::Writing(mut stream, msg) => match stream.write(msg).poll(waker) {
+                        PollState::Ready(n) => *self = Client::Writing(stream, &msg[n.unwrap()..]),
+                        PollState::NotReady => {
+                            *self = Client::Writing(stream, msg);
+                            return PollState::NotReady;
+                        }
+                    },
+                    Client::Reading(mut stream, mut out) => {
+                        let mut buf = [0u8; 1024];
+                        match stream.read(&mut buf).poll(waker) {
+                            PollState::Ready(Ok(0)) => return PollState::Ready(out),
+                            PollState::Ready(n) => out.extend(&buf[..n.unwrap()]),
+                            PollState::NotReady => {
+                                *self = Client::Reading(stream, out);
+                                return PollState::NotReady;
+                            }
+                        }
+                        *self = Client::Reading(stream, out);
+                    }
+                    Client::Done => panic!("Polled a resolved future"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn connect_write_read() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            use std::io::{Read, Write};
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 5];
+            stream.read_exact(&mut buf).unwrap();
+            stream.write_all(&buf.repeat(3)).unwrap();
+        });
+
+        let mut runtime = Runtime::new();
+        let client = Client::Connecting(TcpStream::connect(addr), b"hello");
+        let out = runtime.block_on(timeout(client, Duration::from_secs(5))).unwrap();
+        assert_eq!(out, b"hellohellohello");
+    }
+
+    // accept 一个连接，回一句话就关闭
+    struct Greeter {
+        listener: TcpListener,
+    }
+
+    impl Future for Greeter {
+        type Output = SocketAddr;
+
+        fn poll(&mut self, waker: &Waker) -> PollState<SocketAddr> {
+            match self.listener.accept().poll(waker) {
+                PollState::Ready(Ok((mut stream, peer))) => {
+                    // 刚建立的连接发送缓冲区是空的，这么短的数据一次就能写完
+                    let PollState::Ready(Ok(5)) = stream.poll_write(waker, b"hi!\r\n") else {
+                        panic!("short write");
+                    };
+                    PollState::Ready(peer)
+                }
+                PollState::Ready(Err(e)) => panic!("accept failed: {e}"),
+                PollState::NotReady => PollState::NotReady,
+            }
+        }
+    }
+
+    #[test]
+    fn accept() {
+        let mut runtime = Runtime::new();
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            use std::io::Read;
+            thread::sleep(Duration::from_millis(50));
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            let mut out = String::new();
+            stream.read_to_string(&mut out).unwrap();
+            (stream.local_addr().unwrap(), out)
+        });
+
+        let peer = runtime.block_on(timeout(Greeter { listener }, Duration::from_secs(5))).unwrap();
+        let (local, out) = client.join().unwrap();
+        assert_eq!(peer, local);
+        assert_eq!(out, "hi!\r\n");
+    }
+
+    #[test]
+    fn connect_refused() {
+        // 先 bind 再关掉，拿到一个(多半)没人监听的端口
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let mut runtime = Runtime::new();
+        let result = runtime.block_on(timeout(TcpStream::connect(addr), Duration::from_secs(5)));
+        assert!(result.unwrap().is_err());
+    }
+}
@@ -1,94 +1,158 @@
+// Reactor 本身搬到了 ch20_web::mio_reactor，ReactorServer 也用它；这里只剩下按线程保存的当前 reactor
 use super::runtime::Waker;
-use mio::{net::TcpStream, Events, Interest, Poll, Registry, Token};
-use std::{
-    collections::HashMap,
-    sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc, Mutex, OnceLock,
-    },
-    thread,
-};
-
-type Wakers = Arc<Mutex<HashMap<usize, Waker>>>;
-
-static REACTOR: OnceLock<Reactor> = OnceLock::new();
-
-pub fn reactor() -> &'static Reactor {
-    REACTOR.get().expect("Called outside an runtime context")
+use ch20_web::mio_reactor::{self, Wake};
+use std::{cell::RefCell, marker::PhantomData, sync::Arc};
+
+pub type Reactor = mio_reactor::Reactor<Waker>;
+
+impl Wake for Waker {
+    fn wake(&self) {
+        Waker::wake(self)
+    }
 }
 
-pub struct Reactor {
-    wakers: Wakers,
-    registry: Registry,
-    next_id: AtomicUsize, // used to allocate task id
+// 原来是 static OnceLock，设置一次就不能再换，进程里只能 start 一次。
+// 改成和 executor 的 CURRENT_EXEC 一样按线程保存：future 都是在 executor 线程上 poll 的，
+// 每个 Runtime 结束时清掉，之后可以再建新的；不同线程上的 Runtime 也互不影响(测试可以并行跑)
+thread_local! {
+    static CURRENT: RefCell<Option<Arc<Reactor>>> = const { RefCell::new(None) };
 }
 
-impl Reactor {
-    pub fn register(&self, stream: &mut TcpStream, interest: Interest, id: usize) {
-        self.registry.register(stream, Token(id), interest).unwrap();
-    }
+pub fn reactor() -> Arc<Reactor> {
+    CURRENT.with(|r| r.borrow().clone()).expect("Called outside an runtime context")
+}
 
-    pub fn set_waker(&self, waker: &Waker, id: usize) {
-        let _ = self
-            .wakers
-            .lock()
-            // 不能使用 *waker , 未实现 Copy trait, cannot move out of `*waker` which is behind a shared reference
-            .map(|mut w| w.insert(id, waker.clone()).is_none())
-            .unwrap();
-    }
+/// Keeps the reactor of the current thread running; dropping it stops the
+/// event loop and joins its thread.
+pub struct ReactorHandle {
+    inner: Option<mio_reactor::ReactorHandle<Waker>>,
+    // CURRENT 是按线程保存的，handle 不能送到别的线程上 drop，不然清掉的是那个线程的 CURRENT
+    _not_send: PhantomData<*const ()>,
+}
 
-    pub fn deregister(&self, stream: &mut TcpStream, id: usize) {
-        self.wakers.lock().map(|mut w| w.remove(&id)).unwrap();
-        self.registry.deregister(stream).unwrap();
+impl Drop for ReactorHandle {
+    fn drop(&mut self) {
+        self.inner.take();
+        CURRENT.with(|r| r.borrow_mut().take());
+        println!("Reactor: event loop stopped");
     }
+}
 
-    pub fn next_id(&self) -> usize {
-        /*We don’t care about any happens before/after relationships happening here;
-         we only care about not handing out the same value twice, so Ordering::Relaxed will suffice here
-         */
-        self.next_id.fetch_add(1, Ordering::Relaxed)
+/// Starts the event loop for the current thread.
+///
+/// # Panics
+/// Panics if this thread already has a running reactor.
+pub fn start() -> ReactorHandle {
+    if CURRENT.with(|r| r.borrow().is_some()) {
+        panic!("Reactor already running");
     }
+    let inner = mio_reactor::start().unwrap();
+    CURRENT.with(|r| *r.borrow_mut() = Some(inner.reactor().clone()));
+    ReactorHandle { inner: Some(inner), _not_send: PhantomData }
 }
 
-fn event_loop(mut poll: Poll, wakers: Wakers) {
-    let mut events = Events::with_capacity(100);
-    // TODO: handle shutdown signal
-    loop {
-        poll.poll(&mut events, None).unwrap();
-        for e in events.iter() {
-            // Tokio provides some methods on the Event object to check several
-            // things about the event it reported. For our use in this example, we don’t need to filter events.
-            let Token(id) = e.token();
-            let wakers = wakers.lock().unwrap();
-
-            println!("Reactor: event for task {id}, getting waker ...");
-            // may be removed already
-            if let Some(waker) = wakers.get(&id) {
-                waker.wake();
+#[cfg(test)]
+mod tests {
+    use super::super::future::{Future, PollState};
+    use super::super::runtime::{timeout, Runtime};
+    use super::*;
+    use mio::net::{TcpStream, UdpSocket};
+    use mio::Interest;
+    use std::{
+        io, thread,
+        time::{Duration, Instant},
+    };
+
+    // 在一个 UdpSocket 上等一个数据报
+    struct Recv {
+        socket: UdpSocket,
+        id: Option<usize>,
+    }
+
+    impl Future for Recv {
+        type Output = Vec<u8>;
+
+        fn poll(&mut self, waker: &Waker) -> PollState<Vec<u8>> {
+            let id = match self.id {
+                Some(id) => id,
+                None => {
+                    let id = reactor().next_id();
+                    reactor().register(&mut self.socket, Interest::READABLE, id).unwrap();
+                    self.id = Some(id);
+                    id
+                }
+            };
+            reactor().set_waker(waker, id);
+            let mut buf = [0u8; 64];
+            match self.socket.recv(&mut buf) {
+                Ok(n) => {
+                    reactor().deregister(&mut self.socket, id).unwrap();
+                    PollState::Ready(buf[..n].to_vec())
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => PollState::NotReady,
+                Err(e) => panic!("recv failed: {e}"),
             }
         }
     }
-}
 
-pub fn start() {
-    let wakers = Arc::new(Mutex::new(HashMap::new()));
-    let poll = Poll::new().unwrap();
-    let registry = poll.registry().try_clone().unwrap();
-    // for debugging purposes, I wanted to initialize it to a different start value than our Executor
-    let next_id = AtomicUsize::new(1);
-    let reactor = Reactor {
-        wakers: wakers.clone(),
-        registry,
-        next_id,
-    };
+    #[test]
+    fn any_source_can_be_registered() {
+        let mut runtime = Runtime::new();
+        let socket = UdpSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            let sender = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+            sender.send_to(b"ping", addr).unwrap();
+        });
+        let data = runtime.block_on(timeout(Recv { socket, id: None }, Duration::from_secs(5)));
+        assert_eq!(data.unwrap(), b"ping");
+    }
 
-    REACTOR.set(reactor).ok().expect("Reactor already running");
-    thread::spawn(move || event_loop(poll, wakers));
-    /*
-    the best practice would be to store the JoinHandle returned from spawn so that we can join
-    the thread later on, but our thread has no way to shut down the event loop anyway, so joining it later
-    makes little sense, and we simply discard the handle.
-     */
-}
+    // 先只关心 READABLE(对方什么都不发，不会有事件)，再改成 WRITABLE，应当马上被唤醒
+    struct Writable {
+        stream: TcpStream,
+        id: Option<usize>,
+    }
+
+    impl Future for Writable {
+        type Output = ();
 
+        fn poll(&mut self, waker: &Waker) -> PollState<()> {
+            match self.id {
+                None => {
+                    let id = reactor().next_id();
+                    reactor().register(&mut self.stream, Interest::READABLE, id).unwrap();
+                    reactor().set_waker(waker, id);
+                    reactor().reregister(&mut self.stream, Interest::WRITABLE, id).unwrap();
+                    self.id = Some(id);
+                    PollState::NotReady
+                }
+                Some(id) => {
+                    reactor().deregister(&mut self.stream, id).unwrap();
+                    PollState::Ready(())
+                }
+            }
+        }
+    }
 
+    #[test]
+    fn reregister_changes_interest() {
+        let mut runtime = Runtime::new();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        // 刚连上的 socket 马上就可写，reregister 要是没生效就只能等 timeout 了
+        let start = Instant::now();
+        let result = runtime.block_on(timeout(Writable { stream, id: None }, Duration::from_secs(5)));
+        assert!(result.is_ok());
+        assert!(start.elapsed() < Duration::from_millis(500));
+
+        // 同一个 source 不能注册两次，错误要返回给调用方而不是 panic
+        let mut socket = UdpSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let reactor = reactor();
+        let id = reactor.next_id();
+        reactor.register(&mut socket, Interest::READABLE, id).unwrap();
+        assert!(reactor.register(&mut socket, Interest::READABLE, reactor.next_id()).is_err());
+        reactor.deregister(&mut socket, id).unwrap();
+    }
+}
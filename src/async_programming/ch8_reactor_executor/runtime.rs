@@ -1,11 +1,123 @@
 // P209 new runtime implementation
 
-pub use super::executor::{Executor, spawn, Waker};
+pub use super::executor::{Executor, JoinHandle, spawn, Waker};
 pub use super::reactor::reactor;
+pub use super::timer::{interval, sleep, sleep_until, timeout};
 
-use super::reactor;
+use super::executor;
+use super::future::Future;
+use super::reactor::{self, ReactorHandle};
+use std::marker::PhantomData;
 
-pub fn init() -> Executor {
-    reactor::start();
-    Executor::new()
+/// An executor plus the reactor it waits on. Dropping it stops the event
+/// loop and joins its thread, after which a new `Runtime` can be created on
+/// the same thread.
+///
+/// Not `Send`: the executor and reactor belong to the thread that created it.
+pub struct Runtime {
+    executor: Executor,
+    reactor: Option<ReactorHandle>,
+    // executor 和 reactor 都是 thread_local 的，换了线程 block_on 就找不到它们了
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Runtime {
+    /// # Panics
+    /// Panics if the current thread already has a running `Runtime`.
+    pub fn new() -> Runtime {
+        Runtime {
+            executor: Executor::new(),
+            reactor: Some(reactor::start()),
+            _not_send: PhantomData,
+        }
+    }
+
+    pub fn block_on<F>(&mut self, future: F) -> F::Output
+        where F: Future + 'static, F::Output: 'static {
+        self.executor.block_on(future)
+    }
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Runtime::new()
+    }
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        // 剩下的 task 里可能有登记在 reactor 上的 future，要在 reactor 停掉之前 drop
+        executor::clear();
+        self.reactor.take();
+    }
+}
+
+pub fn init() -> Runtime {
+    Runtime::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::future::PollState;
+    use super::super::timer::Elapsed;
+    use std::time::{Duration, Instant};
+
+    // 数到 n 个 tick 就结束
+    struct Ticks {
+        interval: super::super::timer::Interval,
+        left: u32,
+    }
+
+    impl Future for Ticks {
+        type Output = ();
+
+        fn poll(&mut self, waker: &Waker) -> PollState<()> {
+            while let PollState::Ready(_) = self.interval.poll(waker) {
+                self.left -= 1;
+                if self.left == 0 {
+                    return PollState::Ready(());
+                }
+            }
+            PollState::NotReady
+        }
+    }
+
+    #[test]
+    fn runtimes_can_be_created_one_after_another() {
+        for _ in 0..3 {
+            let mut runtime = Runtime::new();
+            runtime.block_on(sleep(Duration::from_millis(10)));
+        }
+        // 最后一个 Runtime 已经 drop，reactor 不在了
+        assert!(std::panic::catch_unwind(reactor).is_err());
+    }
+
+    // Duration::MAX 当作"不限时"用，不能因为溢出 panic
+    #[test]
+    fn huge_durations_do_not_overflow() {
+        let mut runtime = Runtime::new();
+        let result = runtime.block_on(timeout(sleep(Duration::from_millis(10)), Duration::MAX));
+        assert_eq!(result, Ok(()));
+        assert!(sleep(Duration::MAX).deadline() > Instant::now() + Duration::from_secs(86400 * 365));
+    }
+
+    #[test]
+    fn timers() {
+        let mut runtime = Runtime::new();
+        let start = Instant::now();
+        runtime.block_on(sleep(Duration::from_millis(100)));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        let start = Instant::now();
+        let result = runtime.block_on(timeout(sleep(Duration::from_secs(10)), Duration::from_millis(50)));
+        assert_eq!(result, Err(Elapsed));
+        let result = runtime.block_on(timeout(sleep(Duration::from_millis(10)), Duration::from_secs(10)));
+        assert_eq!(result, Ok(()));
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let start = Instant::now();
+        runtime.block_on(Ticks { interval: interval(Duration::from_millis(20)), left: 3 });
+        assert!(start.elapsed() >= Duration::from_millis(60));
+    }
 }
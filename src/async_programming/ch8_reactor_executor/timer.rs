@@ -0,0 +1,146 @@
+// 定时器 future。到期时间登记在 reactor 里，event loop 的 poll 超时取最近的 deadline，
+// 到期后由 reactor 线程调用 waker，和 I/O 事件走同一条唤醒路径
+use super::future::{Future, PollState};
+use super::reactor::Reactor;
+use super::runtime::{reactor, Waker};
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(after(Instant::now(), duration))
+}
+
+// Instant + Duration 溢出会 panic(比如 Duration::MAX 表示"永远")。
+// 和 tokio 一样，溢出了就当作 30 年以后，反正等不到
+fn after(start: Instant, duration: Duration) -> Instant {
+    start.checked_add(duration).unwrap_or_else(far_future)
+}
+
+fn far_future() -> Instant {
+    Instant::now() + Duration::from_secs(86400 * 365 * 30)
+}
+
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep { deadline, timer: None }
+}
+
+/// Resolves once its deadline has passed.
+pub struct Sleep {
+    deadline: Instant,
+    // 第一次 NotReady 时才向 reactor 登记。记下是哪个 reactor，
+    // 这样 Runtime 结束以后才 drop 的 Sleep 也能正确取消(虽然已经没有意义了)
+    timer: Option<(Arc<Reactor>, usize)>,
+}
+
+impl Sleep {
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    fn cancel(&mut self) {
+        if let Some((reactor, id)) = self.timer.take() {
+            reactor.remove_timer(self.deadline, id);
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(&mut self, waker: &Waker) -> PollState<()> {
+        if Instant::now() >= self.deadline {
+            self.cancel();
+            return PollState::Ready(());
+        }
+        // 和 HttpGetFuture 一样，每次都要更新成最新的 waker
+        let (reactor, id) = self.timer.get_or_insert_with(|| {
+            let reactor = reactor();
+            let id = reactor.next_id();
+            (reactor, id)
+        });
+        reactor.set_timer(self.deadline, waker, *id);
+        PollState::NotReady
+    }
+}
+
+// 还没到期就被丢弃(比如 timeout 里的 future 先完成了)，把 reactor 里的 timer 也删掉
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Ticks every `period`, starting one period from now.
+pub fn interval(period: Duration) -> Interval {
+    assert!(!period.is_zero(), "interval period must be non-zero");
+    Interval { period, sleep: sleep(period) }
+}
+
+/// A future that can be polled again after it resolved: every `Ready` is one
+/// tick and carries the deadline it was scheduled for. Ticks that were missed
+/// because the task ran late are skipped rather than delivered in a burst.
+pub struct Interval {
+    period: Duration,
+    sleep: Sleep,
+}
+
+impl Future for Interval {
+    type Output = Instant;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Instant> {
+        match self.sleep.poll(waker) {
+            PollState::Ready(()) => {
+                let tick = self.sleep.deadline();
+                let mut next = after(tick, self.period);
+                let now = Instant::now();
+                while next <= now {
+                    next += self.period;
+                }
+                self.sleep = sleep_until(next);
+                PollState::Ready(tick)
+            }
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}
+
+/// Error of a [`Timeout`] whose deadline passed first.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("deadline has elapsed")
+    }
+}
+
+impl std::error::Error for Elapsed {}
+
+/// Runs `future` for at most `duration`.
+pub fn timeout<F: Future>(future: F, duration: Duration) -> Timeout<F> {
+    Timeout { future, sleep: sleep(duration) }
+}
+
+pub struct Timeout<F> {
+    future: F,
+    sleep: Sleep,
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        // 先 poll 内部的 future，同时就绪时算它完成了
+        if let PollState::Ready(output) = self.future.poll(waker) {
+            self.sleep.cancel();
+            return PollState::Ready(Ok(output));
+        }
+        match self.sleep.poll(waker) {
+            PollState::Ready(()) => PollState::Ready(Err(Elapsed)),
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}